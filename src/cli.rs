@@ -1,8 +1,13 @@
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use crate::project::root::Root;
 use crate::project::error::Result;
 use crate::project::root::checkout::CheckoutMode;
+use crate::project::root::sparse_checkout::SparseCheckoutAction;
+use crate::project::root::worktrees::WorktreeAction;
+use crate::project::root::file_monitoring::FsmonitorAction;
+use crate::project::root::change_reporting::RenameOptions;
 
 #[derive(Parser)]
 #[command(name = "Gust")]
@@ -11,48 +16,181 @@ pub struct Cli {
     // Tell clap that this is a subcommand, don't try to parse the text into this enum-type
     #[clap(subcommand)]
     pub command: Commands,
+    // Threads used to scan and hash files, one per CPU by default
+    #[arg(short, long, global = true)]
+    pub jobs: Option<NonZeroUsize>
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     Init,
     // Struct-variant commands will still take positional arguments. They can't be tuple-variants because clap doesn't know the arg names
+    // Paths can be files, folders, globs like '*.rs' or exclusions like ':(exclude)docs'
     Add {
-        paths: Vec<PathBuf>
+        paths: Vec<PathBuf>,
+        // Asks which hunks of each modified file to stage
+        #[arg(short, long, conflicts_with_all = ["all", "update"])]
+        patch: bool,
+        // Stages every change, including new and deleted files
+        #[arg(short = 'A', long, conflicts_with = "update")]
+        all: bool,
+        // Only stages changes to tracked files
+        #[arg(short, long)]
+        update: bool
     },
+    // Deletes the files and stages their removal
     Rm {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        // Stops tracking the files but keeps them on disk
+        #[arg(long)]
+        cached: bool,
+        // Removes files even if they have changes that aren't committed
+        #[arg(short, long)]
+        force: bool
+    },
+    Unstage {
+        #[arg(required = true)]
         paths: Vec<PathBuf>
     },
+    // Moves a file or folder and stages the rename. When the destination is a folder, the source is moved inside it
+    Mv {
+        source: PathBuf,
+        destination: PathBuf
+    },
     Commit {
         #[arg(short, long, default_value = "")]
         message: String
     },
-    Status,
-    Log,
+    Status {
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        renames: RenameOptions
+    },
+    Log {
+        revision: Option<String>,
+        // Lists the files each commit changed, with the number of lines
+        #[arg(long)]
+        stat: bool,
+        #[command(flatten)]
+        renames: RenameOptions
+    },
+    // Shows the unstaged changes line by line, or the staged ones, or the changes between two revisions
+    Diff {
+        #[arg(num_args = 1..=2, value_names = ["FROM", "TO"], conflicts_with = "staged")]
+        revisions: Vec<String>,
+        #[arg(long)]
+        staged: bool,
+        #[command(flatten)]
+        renames: RenameOptions
+    },
     Branch {
-        branch_name: Option<String>
+        branch_name: Option<String>,
+        start_point: Option<String>,
+        #[arg(short, long)]
+        delete: bool,
+        // Deletes even if the branch has commits that aren't on any other branch
+        #[arg(short = 'D')]
+        force_delete: bool,
+        #[arg(short = 'm', long = "move", num_args = 2, value_names = ["OLD", "NEW"], conflicts_with = "branch_name")]
+        rename: Option<Vec<String>>,
+        // -v shows the latest commit, -vv also shows the upstream
+        #[arg(short, action = clap::ArgAction::Count)]
+        verbose: u8,
+        #[arg(short = 'u', long, value_name = "UPSTREAM")]
+        set_upstream_to: Option<String>,
+        #[arg(long, conflicts_with = "set_upstream_to")]
+        unset_upstream: bool
     },
     Checkout {
         name: String,
         #[arg(long, short, value_enum)]
         mode: Option<CheckoutMode>,
+        // Discards uncommitted changes and overwrites untracked files
+        #[arg(long, short)]
+        force: bool,
+    },
+    Switch {
+        name: String,
+        #[arg(requires = "create")]
+        start_point: Option<String>,
+        #[arg(short, long, conflicts_with = "orphan")]
+        create: bool,
+        #[arg(long, conflicts_with = "start_point")]
+        orphan: bool
+    },
+    Tag {
+        name: Option<String>,
+        commit: Option<String>,
+        #[arg(short, long)]
+        annotate: bool,
+        #[arg(short, long)]
+        message: Option<String>,
+        #[arg(short, long)]
+        list: bool,
+        #[arg(short, long)]
+        delete: bool
+    },
+    Reflog {
+        name: Option<String>
+    },
+    Restore {
+        #[arg(long, short)]
+        source: Option<String>,
+        #[arg(long, short = 'S')]
+        staged: bool,
+        #[arg(long, short = 'W')]
+        worktree: bool,
+        #[arg(required = true)]
+        paths: Vec<PathBuf>
+    },
+    SparseCheckout {
+        #[command(subcommand)]
+        action: SparseCheckoutAction
+    },
+    Worktree {
+        #[command(subcommand)]
+        action: WorktreeAction
+    },
+    Fsmonitor {
+        #[command(subcommand)]
+        action: FsmonitorAction
     }
 }
 
 impl Commands {
-    pub fn run(&self) -> Result<()> {
+    pub fn run(&self, jobs: Option<NonZeroUsize>) -> Result<()> {
         match self {
             Commands::Init => Root::create_project(),
             other => {
-                let mut project = Root::new()?;
+                let mut project = Root::new(jobs)?;
                 match other {
-                    Commands::Add { paths } => project.add(paths),
-                    Commands::Rm { paths } => project.remove(paths),
+                    Commands::Add { paths, patch: true, .. } => project.add_patch(paths),
+                    Commands::Add { paths, patch: false, all, update } => project.add(paths, *all, *update),
+                    Commands::Rm { paths, cached, force } => project.remove(paths, *cached, *force),
+                    Commands::Unstage { paths } => project.unstage(paths),
+                    Commands::Mv { source, destination } => project.move_path(source, destination),
                     Commands::Commit { message } => project.commit(message.clone()),
-                    Commands::Status => project.status(),
-                    Commands::Log => project.log(),
-                    Commands::Branch { branch_name } => project.branch(branch_name),
-                    Commands::Checkout { mode, name } => project.checkout(mode, name),
+                    Commands::Status { paths, renames } => project.status(paths, renames),
+                    Commands::Log { revision, stat, renames } => project.log(revision, *stat, renames),
+                    Commands::Diff { revisions, staged, renames } => project.diff(revisions, *staged, renames),
+                    Commands::Branch { branch_name, start_point, delete, force_delete, rename, verbose, set_upstream_to, unset_upstream } => {
+                        if let Some(upstream) = set_upstream_to {
+                            project.set_upstream(branch_name, Some(upstream))
+                        } else if *unset_upstream {
+                            project.set_upstream(branch_name, None)
+                        } else {
+                            project.branch(branch_name, start_point, *delete, *force_delete, rename, *verbose)
+                        }
+                    },
+                    Commands::Checkout { mode, name, force } => project.checkout(mode, name, *force),
+                    Commands::Switch { name, start_point, create, orphan } => project.switch(name, *create, *orphan, start_point),
+                    Commands::Tag { name, commit, annotate, message, list, delete } => project.tag(name, commit, *annotate, message, *list, *delete),
+                    Commands::Reflog { name } => project.reflog(name),
+                    Commands::Restore { source, staged, worktree, paths } => project.restore(source, *staged, *worktree, paths),
+                    Commands::SparseCheckout { action } => project.sparse_checkout(action),
+                    Commands::Worktree { action } => project.worktree(action),
+                    Commands::Fsmonitor { action } => project.fsmonitor(action),
                     _ => unreachable!() // Panics if it reaches this
                 }
             }
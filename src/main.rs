@@ -5,7 +5,7 @@ mod cli;
 
 fn main() {
     let cli = cli::Cli::parse();
-    let result = cli.command.run();
+    let result = cli.command.run(cli.jobs);
     if let Err(e) = result {
         eprintln!("{}", e);
     }
@@ -6,4 +6,14 @@ mod staging_area;
 pub mod error;
 pub mod paths;
 mod storable;
-mod head;
\ No newline at end of file
+mod head;
+mod tag;
+mod reflog;
+mod ref_name;
+mod attributes;
+mod stat_cache;
+mod parallel;
+mod diff;
+mod pathspec;
+mod fsmonitor;
+mod renames;
\ No newline at end of file
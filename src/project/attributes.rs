@@ -0,0 +1,109 @@
+use std::fs;
+use serde::{Deserialize, Serialize};
+use super::error::{GustError, Result};
+use super::paths::{glob_matches, RootRelativePath};
+use super::root::RootPath;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+pub(super) enum Eol {
+    #[default]
+    Lf,
+    Crlf
+}
+
+// A line of .gustattributes. Unset attributes are left to earlier lines
+struct AttributeRule {
+    pattern: String,
+    text: Option<bool>,
+    eol: Option<Eol>
+}
+
+pub(super) struct Attributes(Vec<AttributeRule>);
+
+// How a file's content is converted between the working tree and its blob
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+pub(super) struct Conversion {
+    normalize: bool,
+    eol: Eol
+}
+
+impl Attributes {
+    pub fn read(root_path: &RootPath) -> Result<Self> {
+        let attributes_path = root_path.join(".gustattributes");
+        if !attributes_path.as_path().exists() { return Ok(Self(vec![])); }
+        let file = fs::read_to_string(attributes_path.as_path())?;
+
+        let mut rules = Vec::new();
+        for (number, line) in file.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let Some(pattern) = words.next().filter(|p| !p.starts_with('#')) else { continue; };
+            let mut rule = AttributeRule { pattern: pattern.trim_start_matches('/').to_string(), text: None, eol: None };
+            for attribute in words {
+                match attribute {
+                    "text" => rule.text = Some(true),
+                    "-text" | "binary" => rule.text = Some(false),
+                    "eol=lf" => rule.eol = Some(Eol::Lf),
+                    "eol=crlf" => rule.eol = Some(Eol::Crlf),
+                    other if other.starts_with("eol=") => return Err(GustError::User(format!(
+                        ".gustattributes line {}: {} isn't a valid line ending, use eol=lf or eol=crlf", number + 1, other
+                    ))),
+                    // Unknown attributes are ignored, so files written for newer versions still load
+                    _ => {}
+                }
+            }
+            rules.push(rule);
+        }
+        Ok(Self(rules))
+    }
+
+    // Later lines override earlier ones. Setting eol marks the file as text unless it's explicitly binary
+    pub fn conversion_for(&self, path: &RootRelativePath) -> Conversion {
+        let path = path.as_path().to_string_lossy();
+        let mut text = None;
+        let mut eol = None;
+        for rule in self.0.iter().filter(|rule| pattern_matches(&rule.pattern, &path)) {
+            text = rule.text.or(text);
+            eol = rule.eol.or(eol);
+        }
+        Conversion { normalize: text.unwrap_or(eol.is_some()), eol: eol.unwrap_or_default() }
+    }
+}
+
+// Patterns without a slash match the file name in any folder, the rest match the whole path
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    if pattern.contains('/') {
+        glob_matches(pattern, path)
+    } else {
+        glob_matches(pattern, path.rsplit('/').next().unwrap_or(path))
+    }
+}
+
+impl Conversion {
+    // Blobs of text files always use LF, so the same text has the same blob on every platform
+    pub fn to_blob(self, content: Vec<u8>) -> Vec<u8> {
+        if !self.normalize { return content; }
+        let mut normalized = Vec::with_capacity(content.len());
+        let mut bytes = content.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+                continue;
+            }
+            normalized.push(byte);
+        }
+        normalized
+    }
+
+    pub fn to_worktree(self, content: Vec<u8>) -> Vec<u8> {
+        if !self.normalize || self.eol == Eol::Lf { return content; }
+        let mut converted = Vec::with_capacity(content.len());
+        for byte in content {
+            if byte == b'\n' {
+                converted.push(b'\r');
+            }
+            converted.push(byte);
+        }
+        converted
+    }
+
+    pub fn is_identity(self) -> bool { !self.normalize }
+}
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs;
 use serde::{Serialize, Deserialize};
 use crate::project::root::RootPath;
@@ -12,6 +13,31 @@ pub(super) struct Branch {
     commits: Vec<CommitRef>,
     store_path: AbsolutePath,
     pub name: String,
+    pub upstream: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(from = "StoredBranchFormat")]
+pub(super) struct StoredBranch {
+    commits: Vec<CommitRef>,
+    upstream: Option<String>
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredBranchFormat {
+    Current { commits: Vec<CommitRef>, upstream: Option<String> },
+    // Branches used to be stored as just their list of commits
+    Legacy(Vec<CommitRef>)
+}
+
+impl From<StoredBranchFormat> for StoredBranch {
+    fn from(value: StoredBranchFormat) -> Self {
+        match value {
+            StoredBranchFormat::Current { commits, upstream } => Self { commits, upstream },
+            StoredBranchFormat::Legacy(commits) => Self { commits, upstream: None }
+        }
+    }
 }
 
 pub(super) struct DetachedBranch {
@@ -50,7 +76,7 @@ pub trait BranchTrait: ContainsStorePath {
 
 // General implementation
 impl<T: BranchTrait + ProjectStorable> ContainsStorePath for T {
-    fn get_absolute_path(&self) -> &AbsolutePath { &self.store_path() }
+    fn get_absolute_path(&self) -> &AbsolutePath { self.store_path() }
 }
 
 // Branch implementation
@@ -63,22 +89,23 @@ impl BranchTrait for Branch {
         Ok(Self {
             commits: vec![commit_ref],
             store_path: Self::build_absolute_path(&(root_path.clone(), id.to_string())),
-            name: id.to_string()
+            name: id.to_string(),
+            upstream: None
         })
     }
 }
 
 impl ProjectStorable for Branch {
-    type Stored = Vec<CommitRef>;
+    type Stored = StoredBranch;
     type CreationArgs = (RootPath, String);
     fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
-        creation_args.0.join(&format!(".gust/branches/{}.json", creation_args.1))
+        creation_args.0.common_join(format!("branches/{}.json", creation_args.1))
     }
     fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
-        Ok(Self { commits: stored, store_path: Self::build_absolute_path(&creation_args), name: creation_args.1 })
+        Ok(Self { commits: stored.commits, store_path: Self::build_absolute_path(&creation_args), name: creation_args.1, upstream: stored.upstream })
     }
     fn into_stored(&self) -> Cow<'_, Self::Stored> {
-        Cow::Borrowed(&self.commits)
+        Cow::Owned(StoredBranch { commits: self.commits.clone(), upstream: self.upstream.clone() })
     }
 }
 
@@ -87,13 +114,33 @@ impl Branch {
         Self {
             commits: tree,
             store_path: Self::build_absolute_path(&(root_path.clone(), id.to_string())),
-            name: id.to_string()
+            name: id.to_string(),
+            upstream: None
         }
     }
+
+    // Commits that are only on this branch, and commits that are only on the other one
+    pub fn ahead_behind(&self, other: &Branch) -> (usize, usize) {
+        let own_ids: HashSet<&str> = self.commits.iter().map(|c| c.get_commit_id()).collect();
+        let other_ids: HashSet<&str> = other.commits.iter().map(|c| c.get_commit_id()).collect();
+        let ahead = own_ids.difference(&other_ids).count();
+        let behind = other_ids.difference(&own_ids).count();
+        (ahead, behind)
+    }
 }
 
 // DetachedBranch implementation
 
+impl DetachedBranch {
+    pub fn new_from_tree(tree: Vec<CommitRef>, root_path: &RootPath, passed_hash: &str) -> Self {
+        Self {
+            commits: tree,
+            store_path: Self::build_absolute_path(root_path),
+            passed_hash: passed_hash.to_string()
+        }
+    }
+}
+
 impl BranchTrait for DetachedBranch {
     fn commits(&self) -> &Vec<CommitRef> { &self.commits }
     fn commits_mut(&mut self) -> &mut Vec<CommitRef> { &mut self.commits }
@@ -116,8 +163,9 @@ impl ProjectStorable for DetachedBranch {
     type CreationArgs = RootPath;
 
     // ACA ESTA EL BUG, BUSCA EL BRANCH EN DETACHED_HEAD.json PERO EN REALIDAD ESTA EN branches/<passed_hash>.json
+    // Each worktree has its own detached HEAD, so it isn't stored with the shared branches
     fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
-        creation_args.join(".gust/branches/DETACHED_HEAD.json")
+        creation_args.gust_join("branches/DETACHED_HEAD.json")
     }
     fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
         Ok(Self { commits: stored.0, store_path: Self::build_absolute_path(&creation_args), passed_hash: stored.1 })
@@ -4,7 +4,7 @@ use serde::{Serialize, Deserialize};
 use crate::project::root::{Root, RootPath};
 use super::paths::{AbsolutePath, RootRelativePath};
 use super::storable::{ContainsStorePath, ProjectStorable};
-use super::tracked_file::{hash_file, Metadata, TrackedFile};
+use super::tracked_file::{FileMode, TrackedFile};
 use super::error::{Result, GustError};
 use super::staging_area::ChangeType;
 
@@ -34,7 +34,7 @@ impl ProjectStorable for Commit {
     type Stored = StorableCommit;
     type CreationArgs = (RootPath, String);
     fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
-        creation_args.0.join(&format!(".gust/commits/{}.json", creation_args.1))
+        creation_args.0.common_join(format!("commits/{}.json", creation_args.1))
     }
     fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
         Ok(Self {
@@ -60,6 +60,7 @@ impl ContainsStorePath for Commit {
 pub enum FileStatus {
     Added,
     Modified,
+    ModeChanged,
     Unchanged
 }
 
@@ -74,21 +75,24 @@ impl Commit {
             Ok(None)
         }
     }
-    pub fn has_file_changed(&self, relative_path: &RootRelativePath, absolute_path: &AbsolutePath) -> Result<FileStatus> {
+    // blob_id is the file's current blob, usually taken from the stat cache so the file doesn't have to be hashed
+    pub fn has_file_changed(&self, relative_path: &RootRelativePath, absolute_path: &AbsolutePath, blob_id: &str) -> Result<FileStatus> {
         if let Some(tracked_file) = self.data.tree.get(relative_path) {
-            return if tracked_file.metadata != Metadata::new_from_file(absolute_path)? { // Compare hashes if files aren't equal
-                if hash_file(absolute_path.as_path())? != tracked_file.get_blob_id() {
-                    Ok(FileStatus::Modified)
-                } else {
-                    Ok(FileStatus::Unchanged)
-                }
+            return if tracked_file.get_blob_id() != blob_id {
+                Ok(FileStatus::Modified)
+            } else if tracked_file.mode != FileMode::from_file(absolute_path)? {
+                Ok(FileStatus::ModeChanged)
             } else {
-                Ok(FileStatus::Unchanged) // Same metadata = same file
+                Ok(FileStatus::Unchanged)
             }
         }
         Ok(FileStatus::Added) // If it wasn't present, it has been created, and it counts as a change
     }
 
+    pub fn get_file(&self, path: &RootRelativePath) -> Option<&TrackedFile> {
+        self.data.tree.get(path)
+    }
+
     pub fn tree_iterator(&self) -> std::collections::hash_map::Iter<'_, RootRelativePath, TrackedFile> {
         self.data.tree.iter()
     }
@@ -105,13 +109,17 @@ impl CommitRef {
         } else {
             HashMap::new()
         };
+        // Renamed files leave their old path, unless a file was staged there since
+        for from in root.get_staging_area().rename_sources() {
+            tree.remove(&from);
+        }
         for (file, change_type) in root.get_staging_area().get_files() {
             match change_type {
                 ChangeType::Removed => { tree.remove(&file); },
-                _ => {
-                    let absolute_file_path = root.get_path().join(file.as_path());
-                    let tracked_file = TrackedFile::new(&absolute_file_path, root.get_path())?;
-                    tree.insert(file, tracked_file);
+                // Files are committed as they were when staged, whatever happened to them since
+                _ => match root.get_staging_area().get_snapshot(&file) {
+                    Some(snapshot) => { tree.insert(file, snapshot.clone()); },
+                    None => return Err(GustError::User(format!("{} was staged by an older version of gust, add it again", file.display())))
                 }
             };
         }
@@ -135,7 +143,9 @@ impl CommitRef {
         }
     }
     
+    pub fn get_commit_id(&self) -> &str { &self.commit_id }
     pub fn display(&self) -> String { format!("{}: {}", self.metadata.name, self.commit_id) }
+    pub fn display_short(&self) -> String { format!("{} {}", &self.commit_id[..7.min(self.commit_id.len())], self.metadata.name) }
 }
 
 impl CommitMetadata {
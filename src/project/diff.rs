@@ -0,0 +1,217 @@
+// Line diffs between two versions of a file, split into hunks like `diff -u` does
+use std::ops::Range;
+
+// Equal lines shown around each change
+pub(super) const CONTEXT_LINES: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) enum Edit {
+    // Indexes of the line in the old and the new version
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize)
+}
+
+// What to do with a hunk when applying it to the old version
+#[derive(Clone)]
+pub(super) enum HunkChoice {
+    Take,
+    Skip,
+    // The lines that replace the hunk's old lines
+    Replace(Vec<Vec<u8>>)
+}
+
+// Lines keep their line endings, so joining them gives back the content. The last one may not have one
+pub(super) fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&b| b == b'\n').collect()
+}
+
+// Uses Myers' algorithm, so the diff has as few edits as possible
+pub(super) fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    diff_ranges(old, new, 0..old.len(), 0..new.len(), &mut edits);
+    edits
+}
+
+// The linear space version: the middle of the edit path is found and the parts before and after it are diffed on
+// their own. The common start and end are skipped first since most changes are small
+fn diff_ranges<T: PartialEq>(old: &[T], new: &[T], mut old_range: Range<usize>, mut new_range: Range<usize>, edits: &mut Vec<Edit>) {
+    while !old_range.is_empty() && !new_range.is_empty() && old[old_range.start] == new[new_range.start] {
+        edits.push(Edit::Equal(old_range.start, new_range.start));
+        old_range.start += 1;
+        new_range.start += 1;
+    }
+    let mut suffix = 0;
+    while !old_range.is_empty() && !new_range.is_empty() && old[old_range.end - 1] == new[new_range.end - 1] {
+        old_range.end -= 1;
+        new_range.end -= 1;
+        suffix += 1;
+    }
+
+    if old_range.is_empty() {
+        edits.extend(new_range.clone().map(Edit::Insert));
+    } else if new_range.is_empty() {
+        edits.extend(old_range.clone().map(Edit::Delete));
+    } else {
+        let (start, end) = middle_snake(&old[old_range.clone()], &new[new_range.clone()]);
+        let (old_start, new_start) = (old_range.start, new_range.start);
+        diff_ranges(old, new, old_start..old_start + start.0, new_start..new_start + start.1, edits);
+        edits.extend((start.0..end.0).map(|x| Edit::Equal(old_start + x, new_start + x - start.0 + start.1)));
+        diff_ranges(old, new, old_start + end.0..old_range.end, new_start + end.1..new_range.end, edits);
+    }
+
+    edits.extend((0..suffix).map(|i| Edit::Equal(old_range.end + i, new_range.end + i)));
+}
+
+// Searches from both ends at once. Every round d finds the furthest point each diagonal k = x - y reaches with d
+// edits, from the start forwards and from the end backwards. The run of equal lines where the two searches first
+// overlap is in the middle of a shortest edit path. Only the latest round is kept, so memory stays linear
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T]) -> ((usize, usize), (usize, usize)) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    let index = |k: isize| (k + max + 1) as usize;
+    // Backwards, x and y count the lines from the end
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+    let next_x = |furthest: &[isize], k: isize, d: isize| {
+        if k == -d || (k != d && furthest[index(k - 1)] < furthest[index(k + 1)]) {
+            furthest[index(k + 1)]
+        } else {
+            furthest[index(k - 1)] + 1
+        }
+    };
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let start_x = next_x(&forward, k, d);
+            let mut x = start_x;
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+            // The backward diagonal that meets this one, searched up to the previous round
+            let other = delta - k;
+            if delta % 2 != 0 && (-(d - 1)..=d - 1).contains(&other) && x + backward[index(other)] >= n {
+                return ((start_x as usize, (start_x - k) as usize), (x as usize, y as usize));
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let start_x = next_x(&backward, k, d);
+            let mut x = start_x;
+            let mut y = x - k;
+            while x < n && y < m && old[(n - 1 - x) as usize] == new[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index(k)] = x;
+            let other = delta - k;
+            if delta % 2 == 0 && (-d..=d).contains(&other) && forward[index(other)] + x >= n {
+                return (((n - x) as usize, (m - y) as usize), ((n - start_x) as usize, (m - start_x + k) as usize));
+            }
+        }
+    }
+    unreachable!("the searches always meet once the rounds cover every edit")
+}
+
+// Ranges of edits with changes and up to `context` equal lines around them. Changes closer than twice the context
+// share a hunk
+pub(super) fn hunks(edits: &[Edit], context: usize) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    for (i, _) in edits.iter().enumerate().filter(|(_, e)| !matches!(e, Edit::Equal(..))) {
+        match hunks.last_mut() {
+            Some(last) if i <= last.end + context => last.end = (i + 1 + context).min(edits.len()),
+            _ => hunks.push(i.saturating_sub(context)..(i + 1 + context).min(edits.len()))
+        }
+    }
+    hunks
+}
+
+// Splits a hunk between each group of changes. The equal lines between two groups are shared out between them.
+// None if the hunk only has one group
+pub(super) fn split_hunk(edits: &[Edit], hunk: &Range<usize>) -> Option<Vec<Range<usize>>> {
+    let mut pieces = Vec::new();
+    let mut start = hunk.start;
+    let mut i = hunk.start;
+    let mut seen_change = false;
+    while i < hunk.end {
+        if matches!(edits[i], Edit::Equal(..)) {
+            let gap_start = i;
+            while i < hunk.end && matches!(edits[i], Edit::Equal(..)) {
+                i += 1;
+            }
+            if seen_change && i < hunk.end {
+                let cut = gap_start + (i - gap_start).div_ceil(2);
+                pieces.push(start..cut);
+                start = cut;
+            }
+        } else {
+            seen_change = true;
+            i += 1;
+        }
+    }
+    pieces.push(start..hunk.end);
+    (pieces.len() > 1).then_some(pieces)
+}
+
+// "@@ -start,count +start,count @@" with 1-based line numbers. An empty side starts at the line before it
+pub(super) fn hunk_header(edits: &[Edit], hunk: &Range<usize>) -> String {
+    let count = |range: Range<usize>, old: bool| edits[range].iter().filter(|e| match e {
+        Edit::Equal(..) => true,
+        Edit::Delete(_) => old,
+        Edit::Insert(_) => !old
+    }).count();
+    let (old_start, new_start) = (count(0..hunk.start, true), count(0..hunk.start, false));
+    let (old_count, new_count) = (count(hunk.clone(), true), count(hunk.clone(), false));
+    let start = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+    format!("@@ -{},{} +{},{} @@", start(old_start, old_count), old_count, start(new_start, new_count), new_count)
+}
+
+// The hunk's lines prefixed with ' ', '-' or '+'
+pub(super) fn hunk_lines<'a>(edits: &[Edit], hunk: &Range<usize>, old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<(char, &'a [u8])> {
+    edits[hunk.clone()].iter().map(|edit| match *edit {
+        Edit::Equal(o, _) => (' ', old[o]),
+        Edit::Delete(o) => ('-', old[o]),
+        Edit::Insert(n) => ('+', new[n])
+    }).collect()
+}
+
+pub(super) fn print_hunk(edits: &[Edit], hunk: &Range<usize>, old: &[&[u8]], new: &[&[u8]]) {
+    println!("{}", hunk_header(edits, hunk));
+    for (prefix, line) in hunk_lines(edits, hunk, old, new) {
+        let text = String::from_utf8_lossy(line);
+        println!("{}{}", prefix, text.trim_end_matches('\n'));
+        if !line.ends_with(b"\n") {
+            println!("\\ No newline at end of file");
+        }
+    }
+}
+
+// Builds the content with the chosen hunks applied to the old version. Hunks must be sorted and not overlap
+pub(super) fn apply_hunks(edits: &[Edit], choices: &[(Range<usize>, HunkChoice)], old: &[&[u8]], new: &[&[u8]]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut next = 0;
+    let old_side = |result: &mut Vec<u8>, edits: &[Edit]| for edit in edits {
+        if let Edit::Equal(o, _) | Edit::Delete(o) = *edit {
+            result.extend_from_slice(old[o]);
+        }
+    };
+    for (hunk, choice) in choices {
+        old_side(&mut result, &edits[next..hunk.start]);
+        match choice {
+            HunkChoice::Take => for edit in &edits[hunk.clone()] {
+                match *edit {
+                    Edit::Equal(o, _) => result.extend_from_slice(old[o]),
+                    Edit::Insert(n) => result.extend_from_slice(new[n]),
+                    Edit::Delete(_) => {}
+                }
+            },
+            HunkChoice::Skip => old_side(&mut result, &edits[hunk.clone()]),
+            HunkChoice::Replace(lines) => lines.iter().for_each(|line| result.extend_from_slice(line))
+        }
+        next = hunk.end;
+    }
+    old_side(&mut result, &edits[next..]);
+    result
+}
@@ -0,0 +1,337 @@
+// The fsmonitor daemon watches a working tree with inotify and remembers which paths changed. Commands ask it for the
+// paths that changed since their last query, using a token it handed out, instead of scanning the whole tree
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use super::error::Result;
+use super::paths::{AbsolutePath, RootRelativePath};
+use super::root::RootPath;
+use super::storable::{ContainsStorePath, ProjectStorable};
+
+pub(super) const SOCKET_NAME: &str = "fsmonitor.sock";
+
+pub(super) struct MonitorUpdate {
+    // Passed in the next query to get the changes after this one
+    pub token: String,
+    // None when the daemon can't tell what changed since the previous token, so the tree has to be scanned
+    pub changed: Option<Vec<PathBuf>>
+}
+
+// The files found by the last query and the token it got. Together with the changes since that token, they give the
+// current files without scanning. The paths that differed from HEAD back then are kept too, since the daemon only
+// reports them again once they're touched
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(super) struct StoredMonitorState {
+    pub token: Option<String>,
+    pub files: BTreeSet<RootRelativePath>,
+    #[serde(default)]
+    pub differing: BTreeSet<RootRelativePath>,
+    // The commit the differing paths were compared with
+    #[serde(default)]
+    pub head: Option<String>
+}
+
+pub(super) struct MonitorState {
+    pub state: StoredMonitorState,
+    store_path: AbsolutePath
+}
+
+impl ProjectStorable for MonitorState {
+    type Stored = StoredMonitorState;
+    type CreationArgs = RootPath;
+    fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
+        creation_args.gust_join("fsmonitor_state.json")
+    }
+    fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
+        Ok(Self { state: stored, store_path: Self::build_absolute_path(&creation_args) })
+    }
+    fn into_stored(&self) -> Cow<'_, Self::Stored> {
+        Cow::Borrowed(&self.state)
+    }
+}
+
+impl ContainsStorePath for MonitorState {
+    fn get_absolute_path(&self) -> &AbsolutePath { &self.store_path }
+}
+
+#[cfg(target_os = "linux")]
+pub(super) use linux::{is_running, query, run_daemon, stop};
+
+#[cfg(not(target_os = "linux"))]
+pub(super) use unsupported::{is_running, query, run_daemon, stop};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::{HashMap, HashSet};
+    use std::ffi::CString;
+    use std::fs::{self, File};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::os::fd::FromRawFd;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use std::thread;
+    use super::{MonitorUpdate, SOCKET_NAME};
+    use crate::project::error::{GustError, Result};
+    use crate::project::root::RootPath;
+
+    const COOKIE_PREFIX: &str = "fsmonitor-cookie-";
+    const COOKIE_TIMEOUT: Duration = Duration::from_secs(2);
+    // Past this many changes, clients are told to scan instead of getting a huge list
+    const MAX_CHANGES: usize = 100_000;
+    const WATCH_MASK: u32 = libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_CLOSE_WRITE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO
+        | libc::IN_CREATE | libc::IN_DELETE | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_ONLYDIR | libc::IN_DONT_FOLLOW;
+
+    // Tokens are "<epoch>:<sequence>". The epoch changes whenever events are lost, which invalidates every token
+    struct Changes {
+        epoch: u64,
+        sequence: u64,
+        paths: Vec<(u64, PathBuf)>,
+        cookies: HashSet<String>
+    }
+
+    impl Changes {
+        fn token(&self) -> String { format!("{}:{}", self.epoch, self.sequence) }
+
+        fn push(&mut self, path: PathBuf) {
+            self.sequence += 1;
+            self.paths.push((self.sequence, path));
+            if self.paths.len() > MAX_CHANGES {
+                self.forget();
+            }
+        }
+
+        fn forget(&mut self) {
+            self.epoch += 1;
+            self.paths.clear();
+        }
+
+        // Paths changed after the token, or None if the token is from another epoch
+        fn since(&self, token: &str) -> Option<Vec<PathBuf>> {
+            let (epoch, sequence) = token.split_once(':')?;
+            if epoch.parse::<u64>().ok()? != self.epoch {
+                return None;
+            }
+            let sequence: u64 = sequence.parse().ok()?;
+            let mut changed: Vec<PathBuf> = self.paths.iter().filter(|(s, _)| *s > sequence).map(|(_, p)| p.clone()).collect();
+            changed.sort();
+            changed.dedup();
+            Some(changed)
+        }
+    }
+
+    struct Watcher {
+        fd: i32,
+        root: PathBuf,
+        gust_dir: PathBuf,
+        // Watched folder of each watch descriptor, relative to the root
+        folders: HashMap<i32, PathBuf>
+    }
+
+    impl Watcher {
+        fn add_watch(&mut self, absolute: &Path) -> Option<i32> {
+            let path = CString::new(absolute.as_os_str().as_bytes()).ok()?;
+            let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), WATCH_MASK) };
+            (wd >= 0).then_some(wd)
+        }
+
+        // .gust isn't watched with the rest, only the cookies in the worktree's state folder matter
+        fn watch_recursively(&mut self, relative: &Path) {
+            let absolute = self.root.join(relative);
+            if absolute == self.root.join(".gust") || absolute == self.gust_dir {
+                return;
+            }
+            let Some(wd) = self.add_watch(&absolute) else { return; };
+            self.folders.insert(wd, relative.to_path_buf());
+            for entry in fs::read_dir(&absolute).into_iter().flatten().flatten() {
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    self.watch_recursively(&relative.join(entry.file_name()));
+                }
+            }
+        }
+    }
+
+    pub fn run_daemon(root: &RootPath) -> Result<()> {
+        let socket_path = root.gust_join(SOCKET_NAME);
+        if is_running(root) {
+            return Err(GustError::User("The fsmonitor daemon is already running".into()));
+        }
+        let _ = fs::remove_file(socket_path.as_path());
+        let listener = UnixListener::bind(socket_path.as_path())?;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut watcher = Watcher {
+            fd,
+            root: root.as_path().to_path_buf(),
+            gust_dir: root.gust_dir().to_path_buf(),
+            folders: HashMap::new()
+        };
+        let gust_wd = watcher.add_watch(&watcher.gust_dir.clone()).ok_or_else(std::io::Error::last_os_error)?;
+        watcher.watch_recursively(Path::new(""));
+        // Otherwise the daemon's working directory keeps the root alive, and its deletion is never reported
+        std::env::set_current_dir("/")?;
+
+        // Starting from the current time means tokens from a previous daemon are never accepted
+        let epoch = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        let changes = Arc::new((Mutex::new(Changes { epoch, sequence: 0, paths: Vec::new(), cookies: HashSet::new() }), Condvar::new()));
+        let reader_changes = Arc::clone(&changes);
+        let reader_socket = socket_path.as_path().to_path_buf();
+        thread::spawn(move || read_events(watcher, gust_wd, &reader_changes, &reader_socket));
+
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue; };
+            if handle_client(stream, &changes).unwrap_or(false) {
+                let _ = fs::remove_file(socket_path.as_path());
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    fn read_events(mut watcher: Watcher, gust_wd: i32, changes: &(Mutex<Changes>, Condvar), socket_path: &Path) {
+        let mut inotify = unsafe { File::from_raw_fd(watcher.fd) };
+        let mut buffer = vec![0u8; 64 * 1024];
+        let header_size = size_of::<libc::inotify_event>();
+        while let Ok(read) = inotify.read(&mut buffer) {
+            let mut offset = 0;
+            while offset + header_size <= read {
+                let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const _) };
+                let name_bytes = &buffer[offset + header_size..offset + header_size + event.len as usize];
+                let name = std::ffi::OsStr::from_bytes(name_bytes.split(|&b| b == 0).next().unwrap_or(&[]));
+                offset += header_size + event.len as usize;
+
+                let (lock, cookie_seen) = changes;
+                let mut changes = lock.lock().unwrap();
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    changes.forget();
+                    continue;
+                }
+                if event.wd == gust_wd {
+                    if event.mask & libc::IN_CREATE != 0 && name.to_string_lossy().starts_with(COOKIE_PREFIX) {
+                        changes.cookies.insert(name.to_string_lossy().to_string());
+                        cookie_seen.notify_all();
+                    }
+                    continue;
+                }
+                let Some(folder) = watcher.folders.get(&event.wd).cloned() else { continue; };
+                if event.mask & libc::IN_IGNORED != 0 {
+                    watcher.folders.remove(&event.wd);
+                    continue;
+                }
+                // The daemon has nothing left to watch once the working tree is gone. The socket keeps .gust and the
+                // root from being freed, so their own deletion isn't reported, only the removal of .gust from the root
+                let is_root = folder.as_os_str().is_empty();
+                let root_gone = is_root && event.mask & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF) != 0;
+                let gust_removed = is_root && name == ".gust" && event.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0;
+                if root_gone || gust_removed {
+                    let _ = fs::remove_file(socket_path);
+                    std::process::exit(0);
+                }
+
+                let path = folder.join(name);
+                // Files can be created in a new folder before it's watched, so clients rescan the whole folder
+                if event.mask & libc::IN_ISDIR != 0 && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                    watcher.watch_recursively(&path);
+                }
+                changes.push(path);
+            }
+        }
+        // Without events the answers would be wrong, so the daemon stops and commands go back to scanning
+        let _ = fs::remove_file(socket_path);
+        std::process::exit(1);
+    }
+
+    // Requests are "query <token> <cookie>" or "stop". Returns true if the daemon has to stop
+    fn handle_client(stream: UnixStream, changes: &(Mutex<Changes>, Condvar)) -> Result<bool> {
+        stream.set_read_timeout(Some(COOKIE_TIMEOUT))?;
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+        let mut words = request.split_whitespace();
+        let mut stream = stream;
+        match (words.next(), words.next(), words.next()) {
+            (Some("stop"), _, _) => {
+                stream.write_all(b"ok\n")?;
+                Ok(true)
+            },
+            (Some("query"), Some(token), Some(cookie)) => {
+                // The cookie is created right before the query, so once its event arrives, every change the client
+                // made before asking has been recorded too
+                let (lock, cookie_seen) = changes;
+                let (mut changes, timeout) = cookie_seen.wait_timeout_while(lock.lock().unwrap(), COOKIE_TIMEOUT, |c| !c.cookies.contains(cookie)).unwrap();
+                changes.cookies.remove(cookie);
+                let mut response = format!("{}\n", changes.token());
+                match changes.since(token).filter(|_| !timeout.timed_out()) {
+                    Some(paths) if paths.iter().all(|p| !p.as_os_str().as_bytes().contains(&b'\n')) => {
+                        for path in paths {
+                            response.push_str(&format!("{}\n", path.display()));
+                        }
+                    },
+                    _ => response.push_str("full\n")
+                }
+                drop(changes);
+                stream.write_all(response.as_bytes())?;
+                Ok(false)
+            },
+            _ => Ok(false)
+        }
+    }
+
+    fn connect(root: &RootPath) -> Option<UnixStream> {
+        let stream = UnixStream::connect(root.gust_join(SOCKET_NAME).as_path()).ok()?;
+        stream.set_read_timeout(Some(COOKIE_TIMEOUT * 2)).ok()?;
+        Some(stream)
+    }
+
+    pub fn is_running(root: &RootPath) -> bool { connect(root).is_some() }
+
+    // Returns None if the daemon isn't running or doesn't answer, so the caller scans instead
+    pub fn query(root: &RootPath, token: Option<&str>) -> Option<MonitorUpdate> {
+        let mut stream = connect(root)?;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+        let cookie = format!("{}{}-{}", COOKIE_PREFIX, std::process::id(), nanos);
+        let cookie_path = root.gust_join(&cookie);
+        fs::write(cookie_path.as_path(), "").ok()?;
+        let result = (|| {
+            stream.write_all(format!("query {} {}\n", token.unwrap_or("-"), cookie).as_bytes()).ok()?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response).ok()?;
+            let mut lines = response.lines();
+            let token = lines.next()?.to_string();
+            let rest: Vec<&str> = lines.collect();
+            let changed = if rest == ["full"] { None } else { Some(rest.into_iter().map(PathBuf::from).collect()) };
+            Some(MonitorUpdate { token, changed })
+        })();
+        let _ = fs::remove_file(cookie_path.as_path());
+        result
+    }
+
+    pub fn stop(root: &RootPath) -> Result<()> {
+        let Some(mut stream) = connect(root) else {
+            return Err(GustError::User("The fsmonitor daemon isn't running".into()));
+        };
+        stream.write_all(b"stop\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod unsupported {
+    use super::MonitorUpdate;
+    use crate::project::error::{GustError, Result};
+    use crate::project::root::RootPath;
+
+    pub fn run_daemon(_root: &RootPath) -> Result<()> {
+        Err(GustError::User("The fsmonitor daemon is only supported on Linux".into()))
+    }
+    pub fn is_running(_root: &RootPath) -> bool { false }
+    pub fn query(_root: &RootPath, _token: Option<&str>) -> Option<MonitorUpdate> { None }
+    pub fn stop(root: &RootPath) -> Result<()> { run_daemon(root) }
+}
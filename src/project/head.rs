@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::fs;
 use crate::project::branch::{Branch, BranchTrait, DetachedBranch};
 use crate::project::root::RootPath;
 use crate::project::error::Result;
@@ -42,32 +43,41 @@ impl Head {
 
     pub fn display(&self) -> String {
         match self {
-            Self::Attached(branch) => {
-                format!("Commit history of {} branch:\n", branch.name).to_string() +  branch.display().as_str()
-            },
-            Self::Detached(branch) => {
-                format!("Commit history of detached HEAD(commit {}):\n", branch.passed_hash).to_string() +  branch.display().as_str()
-            }
+            Self::Attached(branch) => self.display_title() + "\n" + branch.display().as_str(),
+            Self::Detached(branch) => self.display_title() + "\n" + branch.display().as_str()
+        }
+    }
+
+    pub fn display_title(&self) -> String {
+        match self {
+            Self::Attached(branch) => format!("Commit history of {} branch:", branch.name),
+            Self::Detached(branch) => format!("Commit history of detached HEAD(commit {}):", branch.passed_hash)
         }
     }
 
+    // Reads which branch a HEAD is attached to without loading the branch, e.g. the HEAD of another worktree
+    pub fn stored_branch_name(root_path: &RootPath) -> Result<Option<String>> {
+        let path = Self::build_absolute_path(root_path);
+        if !path.exists() { return Ok(None); }
+        Ok(match serde_json::from_reader(fs::File::open(path.as_path())?)? {
+            StoredHead::Attached(name) => Some(name),
+            StoredHead::Detached => None
+        })
+    }
+
     pub fn handle_checkout(&self) -> Result<()> {
         match self {
             Self::Attached(branch) => branch.handle_checkout(),
             Self::Detached(branch) => branch.handle_checkout(),
         }
     }
-
-    fn default(root_path: &RootPath) -> Result<Self> {
-        Ok(Self::Attached(Branch::create((root_path.clone(), "main".into()))?))
-    }
 }
 
 impl ProjectStorable for Head {
     type Stored = StoredHead;
     type CreationArgs = RootPath;
     fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
-        creation_args.join(".gust/HEAD.json")
+        creation_args.gust_join("HEAD.json")
     }
     fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Head> {
         Ok(match stored {
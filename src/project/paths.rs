@@ -1,5 +1,5 @@
 use serde::{Serialize, Deserialize};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use crate::project::root::RootPath;
 use super::error::{GustError, Result as GustResult};
 
@@ -7,7 +7,7 @@ use super::error::{GustError, Result as GustResult};
 pub(crate) struct CliPath(PathBuf);
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub(super) struct AbsolutePath(PathBuf);
-#[derive(Serialize, Deserialize, Debug, Hash, PartialEq, Eq, Clone)]
+#[derive(Serialize, Deserialize, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub(super) struct RootRelativePath(PathBuf);
 
 impl From<&Path> for CliPath {
@@ -23,7 +23,7 @@ impl TryFrom<CliPath> for AbsolutePath {
         } else {
             std::env::current_dir()?.join(&value.0)
         };
-        if !joined.exists() {
+        if std::fs::symlink_metadata(&joined).is_err() {
             Err(GustError::User(format!("Path is not inside root: {}", joined.display())))
         } else {
             Ok(Self(joined))
@@ -31,6 +31,27 @@ impl TryFrom<CliPath> for AbsolutePath {
     }
 }
 
+impl CliPath {
+    // Same as the TryFrom conversion, but allows paths that don't exist (e.g. deleted files). ".." and "." are resolved
+    // lexically because the path can't be canonicalized if it isn't on disk
+    pub(super) fn to_unchecked_absolute(&self) -> GustResult<AbsolutePath> {
+        let joined = if self.0.is_absolute() {
+            self.0.clone()
+        } else {
+            std::env::current_dir()?.join(&self.0)
+        };
+        let mut normalized = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::CurDir => {},
+                Component::ParentDir => { normalized.pop(); },
+                other => normalized.push(other.as_os_str())
+            }
+        }
+        Ok(AbsolutePath(normalized))
+    }
+}
+
 impl AbsolutePath {
     pub fn from_absolute_path(path: &Path) -> Self {
         if !path.is_absolute() {
@@ -40,17 +61,56 @@ impl AbsolutePath {
     }
     pub fn as_path(&self) -> &Path { self.0.as_path() }
     pub fn strip_prefix(&self, prefix: &Path) -> &Path { self.0.strip_prefix(prefix).unwrap() }
-    pub fn is_dir(&self) -> bool { self.0.is_dir() }
+    // None of these follow symlinks, a link is tracked like a file even if it points to a folder or to nothing
+    pub fn is_dir(&self) -> bool { std::fs::symlink_metadata(&self.0).is_ok_and(|m| m.is_dir()) }
+    pub fn is_symlink(&self) -> bool { self.0.is_symlink() }
+    pub fn exists(&self) -> bool { std::fs::symlink_metadata(&self.0).is_ok() }
+    pub fn is_file(&self) -> bool { self.exists() && !self.is_dir() }
+}
+
+// Removes the folders that contain path while they're empty, without going above base
+pub(super) fn remove_empty_parent_dirs(path: &Path, base: &Path) -> GustResult<()> {
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir == base || !dir.starts_with(base) || !dir.is_dir() || std::fs::read_dir(dir)?.next().is_some() {
+            break;
+        }
+        std::fs::remove_dir(dir)?;
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+// * and ? don't match slashes, ** matches any number of folders
+pub(super) fn glob_matches(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[u8], path: &[u8]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            [b'*', b'*', b'/', rest @ ..] => {
+                matches(rest, path) || path.iter().enumerate().any(|(i, &c)| c == b'/' && matches(rest, &path[i + 1..]))
+            },
+            [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            [b'*', rest @ ..] => {
+                let segment_end = path.iter().position(|&c| c == b'/').unwrap_or(path.len());
+                (0..=segment_end).any(|i| matches(rest, &path[i..]))
+            },
+            [b'?', rest @ ..] => path.first().is_some_and(|&c| c != b'/') && matches(rest, &path[1..]),
+            [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..])
+        }
+    }
+    matches(pattern.as_bytes(), path.as_bytes())
 }
 
 impl RootRelativePath {
     pub fn new(path: &AbsolutePath, root_path: &RootPath) -> GustResult<Self> {
-        if !root_path.is_inside_root(&path) {
+        if !root_path.is_inside_root(path) {
             Err(GustError::User(format!("{} isn't inside the project", path.0.display())))
         } else {
             Ok(Self(path.strip_prefix(root_path.as_path()).into()))
         }
     }
+    // Used for paths that are already known to be relative to the root, like the ones stored in trees
+    pub fn from_relative_path(path: &Path) -> Self { Self(path.into()) }
     pub fn display(&self) -> String { self.0.display().to_string() }
     pub fn as_path(&self) -> &Path { self.0.as_path() }
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+// Pathspecs select files by path, folder or glob, relative to the current directory. They're matched against the paths
+// gust knows about instead of the disk, so they also select deleted files
+use std::path::{Path, PathBuf};
+use super::error::{GustError, Result};
+use super::paths::{glob_matches, CliPath, RootRelativePath};
+use super::root::RootPath;
+
+const EXCLUDE_PREFIXES: [&str; 3] = [":(exclude)", ":!", ":^"];
+
+pub(super) struct Pathspec {
+    includes: Vec<(String, Pattern)>,
+    excludes: Vec<Pattern>
+}
+
+enum Pattern {
+    // A file, or everything inside a folder
+    Path(PathBuf),
+    // Matched against the path relative to base. A glob without slashes is matched against each file and folder name
+    // below base, so "*.rs" selects them at any depth like it does in git
+    Glob { base: PathBuf, glob: String }
+}
+
+impl Pathspec {
+    pub fn parse(args: &[PathBuf], root_path: &RootPath) -> Result<Self> {
+        let mut pathspec = Self { includes: Vec::new(), excludes: Vec::new() };
+        for arg in args {
+            let arg = arg.to_string_lossy();
+            match EXCLUDE_PREFIXES.iter().find_map(|prefix| arg.strip_prefix(prefix)) {
+                Some(excluded) => pathspec.excludes.push(Pattern::parse(excluded, root_path)?),
+                None => pathspec.includes.push((arg.to_string(), Pattern::parse(&arg, root_path)?))
+            }
+        }
+        Ok(pathspec)
+    }
+
+    // For patterns that are already relative to the root, like the sparse checkout ones. They aren't resolved against
+    // the current directory, and they don't have to exist
+    pub fn from_root_patterns(patterns: &[String]) -> Self {
+        let includes = patterns.iter().map(|pattern| (pattern.clone(), Pattern::from_root_relative(pattern))).collect();
+        Self { includes, excludes: Vec::new() }
+    }
+
+    // The included paths as they were given
+    pub fn args(&self) -> impl Iterator<Item = &str> {
+        self.includes.iter().map(|(arg, _)| arg.as_str())
+    }
+
+    // True when no path was given. Excludes alone still select everything else
+    pub fn is_empty(&self) -> bool { self.includes.is_empty() && self.excludes.is_empty() }
+
+    pub fn matches(&self, path: &RootRelativePath) -> bool {
+        (self.includes.is_empty() || self.includes.iter().any(|(_, pattern)| pattern.matches(path.as_path())))
+            && !self.excludes.iter().any(|pattern| pattern.matches(path.as_path()))
+    }
+
+    // Every path that was given has to select one of the files, or be on disk, so typos aren't silently ignored
+    pub fn ensure_matches_any<'a>(&self, files: impl Iterator<Item = &'a RootRelativePath> + Clone, root_path: &RootPath) -> Result<()> {
+        let is_on_disk = |pattern: &Pattern| matches!(pattern, Pattern::Path(path) if root_path.join(path).exists());
+        match self.unmatched(files, is_on_disk) {
+            Some(arg) => Err(GustError::User(format!("Pathspec '{}' did not match any files", arg))),
+            None => Ok(())
+        }
+    }
+
+    // For commands that only work on tracked files, where a file on disk isn't enough
+    pub fn ensure_matches_tracked<'a>(&self, files: impl Iterator<Item = &'a RootRelativePath> + Clone) -> Result<()> {
+        match self.unmatched(files, |_| false) {
+            Some(arg) => Err(GustError::User(format!("Pathspec '{}' did not match any tracked files", arg))),
+            None => Ok(())
+        }
+    }
+
+    fn unmatched<'a>(&self, files: impl Iterator<Item = &'a RootRelativePath> + Clone, is_on_disk: impl Fn(&Pattern) -> bool) -> Option<&str> {
+        self.includes.iter()
+            .find(|(_, pattern)| !is_on_disk(pattern) && !files.clone().any(|file| pattern.matches(file.as_path())))
+            .map(|(arg, _)| arg.as_str())
+    }
+}
+
+impl Pattern {
+    // The folders before the first wildcard are resolved like a normal path, the rest is kept as a glob
+    fn parse(arg: &str, root_path: &RootPath) -> Result<Self> {
+        let (literal, glob) = split_glob(arg);
+        let literal = if literal.is_empty() { "/" } else { literal };
+        let absolute_path = CliPath::from(Path::new(literal)).to_unchecked_absolute()?;
+        if !root_path.is_inside_root(&absolute_path) {
+            return Err(GustError::User(format!("Path {} is not inside the project", absolute_path.as_path().display())));
+        }
+        let base = RootRelativePath::new(&absolute_path, root_path)?.as_path().to_path_buf();
+        Ok(match glob {
+            Some(glob) => Pattern::Glob { base, glob: glob.to_string() },
+            None => Pattern::Path(base)
+        })
+    }
+
+    fn from_root_relative(arg: &str) -> Self {
+        let (literal, glob) = split_glob(arg);
+        let base = PathBuf::from(if literal == "." { "" } else { literal });
+        match glob {
+            Some(glob) => Pattern::Glob { base, glob: glob.to_string() },
+            None => Pattern::Path(base)
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            Pattern::Path(prefix) => path.starts_with(prefix),
+            Pattern::Glob { base, glob } => {
+                let Ok(rest) = path.strip_prefix(base) else { return false; };
+                if glob.contains('/') {
+                    // A match on a folder selects everything inside it
+                    rest.ancestors().filter(|a| !a.as_os_str().is_empty()).any(|a| glob_matches(glob, &a.to_string_lossy()))
+                } else {
+                    rest.iter().any(|name| glob_matches(glob, &name.to_string_lossy()))
+                }
+            }
+        }
+    }
+}
+
+// Splits at the last slash before the first wildcard, "src/*.rs" becomes "src" and "*.rs"
+fn split_glob(arg: &str) -> (&str, Option<&str>) {
+    match arg.find(['*', '?']) {
+        Some(wildcard) => match arg[..wildcard].rfind('/') {
+            Some(slash) => (&arg[..slash], Some(&arg[slash + 1..])),
+            None => (".", Some(arg))
+        },
+        None => (arg, None)
+    }
+}
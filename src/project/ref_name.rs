@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::Path;
+use super::error::{GustError, Result};
+use super::paths::remove_empty_parent_dirs;
+
+// Names that already have a meaning and can't be used for a branch or a tag
+const RESERVED_NAMES: [&str; 2] = ["HEAD", "DETACHED_HEAD"];
+// Characters that are part of the revision syntax or aren't allowed in file names
+const FORBIDDEN_CHARACTERS: [char; 9] = [' ', '~', '^', ':', '?', '*', '[', '\\', '"'];
+
+// Validates branch and tag names. They're stored as files, and the slashes create folders
+pub(super) fn validate_ref_name(name: &str, ref_type: &str) -> Result<()> {
+    let invalid = |reason: &str| Err(GustError::User(format!("'{}' is not a valid {} name: {}", name, ref_type, reason)));
+
+    if name.is_empty() {
+        return invalid("it's empty");
+    }
+    if RESERVED_NAMES.contains(&name) {
+        return invalid("the name is reserved");
+    }
+    if let Some(c) = name.chars().find(|c| c.is_ascii_control() || FORBIDDEN_CHARACTERS.contains(c)) {
+        return invalid(&format!("it contains {:?}", c));
+    }
+    if name.contains("..") || name.contains("@{") || name == "@" {
+        return invalid("it contains '..' or '@{', which are part of the revision syntax");
+    }
+    if name.ends_with('.') {
+        return invalid("it ends with '.'");
+    }
+    for component in name.split('/') {
+        if component.is_empty() {
+            return invalid("it has an empty component, check for leading, trailing or double slashes");
+        }
+        if component.starts_with('.') {
+            return invalid("a component starts with '.'");
+        }
+    }
+    Ok(())
+}
+
+// Names given to look up an existing ref are joined to the refs folder too, so they can't be allowed to leave it. This
+// is looser than validate_ref_name, so any ref that was stored can still be found
+pub(super) fn is_lookup_safe(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('/')
+        && !name.chars().any(|c| c.is_ascii_control() || c == '\\' || c == ':')
+        && name.split('/').all(|component| !component.is_empty() && component != "." && component != "..")
+}
+
+pub(super) fn check_ref_lookup(name: &str, ref_type: &str) -> Result<()> {
+    if !is_lookup_safe(name) {
+        return Err(GustError::User(format!("'{}' is not a valid {} name", name, ref_type)));
+    }
+    Ok(())
+}
+
+// Lists the refs stored as <name>.json files under dir, including the ones in nested folders
+pub(super) fn list_ref_names(dir: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    if dir.exists() {
+        collect_ref_names(dir, dir, &mut names)?;
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn collect_ref_names(base: &Path, dir: &Path, names: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_ref_names(base, &path, names)?;
+        } else if path.extension().is_some_and(|e| e == "json") {
+            let relative = path.strip_prefix(base).unwrap().with_extension("");
+            let components: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+            names.push(components.join("/"));
+        }
+    }
+    Ok(())
+}
+
+// Deletes a ref file and the folders of its namespace that are left empty, up to base
+pub(super) fn remove_ref_file(path: &Path, base: &Path) -> Result<()> {
+    fs::remove_file(path)?;
+    remove_empty_parent_dirs(path, base)
+}
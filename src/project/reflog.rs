@@ -0,0 +1,86 @@
+use std::fs;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::project::root::RootPath;
+use super::paths::AbsolutePath;
+use super::error::{GustError, Result};
+
+// Reflogs aren't ProjectStorable because they're never rewritten, every update is appended as a JSON line
+pub(super) struct Reflog {
+    path: AbsolutePath,
+    pub name: String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(super) struct ReflogEntry {
+    pub old: Option<String>,
+    pub new: Option<String>,
+    pub operation: String,
+    pub timestamp: u64
+}
+
+impl Reflog {
+    pub fn for_ref(root_path: &RootPath, name: &str) -> Self {
+        let path = if name == "HEAD" {
+            root_path.gust_join("logs/HEAD.jsonl")
+        } else {
+            root_path.common_join(format!("logs/branches/{}.jsonl", name))
+        };
+        Self { path, name: name.to_string() }
+    }
+
+    pub fn exists(&self) -> bool { self.path.as_path().exists() }
+
+    pub fn append(&self, old: Option<String>, new: Option<String>, operation: &str) -> Result<()> {
+        if let Some(parent) = self.path.as_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let entry = ReflogEntry { old, new, operation: operation.to_string(), timestamp: current_timestamp() };
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(self.path.as_path())?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
+
+    // Oldest entry first
+    pub fn entries(&self) -> Result<Vec<ReflogEntry>> {
+        if !self.exists() { return Ok(Vec::new()); }
+        let mut entries = Vec::new();
+        for line in fs::read_to_string(self.path.as_path())?.lines().filter(|l| !l.is_empty()) {
+            entries.push(serde_json::from_str(line)?);
+        }
+        Ok(entries)
+    }
+
+    // <name>@{n} is the value the ref had n updates ago
+    pub fn get(&self, n: usize) -> Result<String> {
+        let entries = self.entries()?;
+        if n >= entries.len() {
+            return Err(GustError::User(format!("{}@{{{}}} is past the end of the reflog, which has {} entries", self.name, n, entries.len())));
+        }
+        entries[entries.len() - 1 - n].new.clone()
+            .ok_or_else(|| GustError::User(format!("{}@{{{}}} doesn't point to a commit", self.name, n)))
+    }
+
+    pub fn rename(&self, root_path: &RootPath, new_name: &str) -> Result<Self> {
+        let renamed = Self::for_ref(root_path, new_name);
+        if self.exists() {
+            if let Some(parent) = renamed.path.as_path().parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(self.path.as_path(), renamed.path.as_path())?;
+        }
+        Ok(renamed)
+    }
+}
+
+impl ReflogEntry {
+    pub fn display(&self, name: &str, index: usize) -> String {
+        let short_hash: String = self.new.as_deref().unwrap_or("0000000").chars().take(7).collect();
+        format!("{} {}@{{{}}}: {}", short_hash, name, index, self.operation)
+    }
+}
+
+pub(super) fn current_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
@@ -0,0 +1,165 @@
+// Finds the added files of a comparison that are really renames of removed files, or copies of other files. Identical
+// files are always paired, files with similar content only when a threshold is given
+use std::collections::HashMap;
+use super::diff::{self, Edit};
+use super::error::Result;
+use super::paths::RootRelativePath;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) enum PairKind {
+    Renamed,
+    Copied
+}
+
+#[derive(Debug)]
+pub(super) struct Pair {
+    pub kind: PairKind,
+    pub from: RootRelativePath,
+    pub to: RootRelativePath,
+    // How much of the content is the same, in percent
+    pub similarity: u8
+}
+
+// A file on one side of the comparison
+pub(super) struct Candidate {
+    pub path: RootRelativePath,
+    pub blob_id: String
+}
+
+pub(super) struct Candidates {
+    // Only on the old side
+    pub removed: Vec<Candidate>,
+    // Only on the new side
+    pub added: Vec<Candidate>,
+    // Old files that are on both sides. Copies can come from any of them, but like git only the modified ones are
+    // compared by content, since comparing against every file would be slow
+    pub kept: Vec<Candidate>,
+    pub modified: Vec<Candidate>
+}
+
+// Sources are preferred in the order they're given
+pub(super) fn find_pairs(
+    candidates: &Candidates,
+    threshold: Option<u8>,
+    copies: bool,
+    read_old: impl Fn(&RootRelativePath) -> Result<Vec<u8>>,
+    read_new: impl Fn(&RootRelativePath) -> Result<Vec<u8>>
+) -> Result<Vec<Pair>> {
+    let Candidates { removed, added, kept, modified } = candidates;
+    // Empty files are all identical, so pairing them would be a guess
+    let empty_blob = sha256::digest(b"".as_slice());
+    let mut pairs = Vec::new();
+    let mut renamed = vec![false; removed.len()];
+    let mut paired = vec![false; added.len()];
+    let pair = |kind, from: &RootRelativePath, to: &RootRelativePath, similarity| Pair { kind, from: from.clone(), to: to.clone(), similarity };
+
+    // A file that kept its name, only in another folder, is the better guess when several files are identical
+    for (i, file) in added.iter().enumerate().filter(|(_, f)| f.blob_id != empty_blob) {
+        let same_name = |j: &usize| removed[*j].path.as_path().file_name() == file.path.as_path().file_name();
+        let mut identical = (0..removed.len()).filter(|&j| !renamed[j] && removed[j].blob_id == file.blob_id);
+        if let Some(j) = identical.clone().find(same_name).or_else(|| identical.next()) {
+            renamed[j] = true;
+            paired[i] = true;
+            pairs.push(pair(PairKind::Renamed, &removed[j].path, &file.path, 100));
+        }
+    }
+    if copies {
+        let mut by_blob: HashMap<&str, &RootRelativePath> = HashMap::new();
+        for source in kept.iter().chain(removed) {
+            by_blob.entry(source.blob_id.as_str()).or_insert(&source.path);
+        }
+        for (i, file) in added.iter().enumerate().filter(|(_, f)| f.blob_id != empty_blob) {
+            if !paired[i] && let Some(source) = by_blob.get(file.blob_id.as_str()) {
+                paired[i] = true;
+                pairs.push(pair(PairKind::Copied, source, &file.path, 100));
+            }
+        }
+    }
+
+    let Some(threshold) = threshold else {
+        pairs.sort_by(|a, b| a.to.cmp(&b.to));
+        return Ok(pairs);
+    };
+    let new_contents = added.iter().enumerate()
+        .filter(|(i, _)| !paired[*i])
+        .map(|(i, file)| Ok((i, read_new(&file.path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let old_contents = removed.iter().enumerate()
+        .filter(|(j, _)| !renamed[*j])
+        .map(|(j, file)| Ok((j, read_old(&file.path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    // The most similar files are paired first. The sort is stable, so ties keep the order of the paths
+    let mut scores = Vec::new();
+    for (i, new) in &new_contents {
+        for (j, old) in &old_contents {
+            if let Some(score) = similarity_above(old, new, threshold) {
+                scores.push((score, *i, *j));
+            }
+        }
+    }
+    scores.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    for (score, i, j) in scores {
+        if !paired[i] && !renamed[j] {
+            paired[i] = true;
+            renamed[j] = true;
+            pairs.push(pair(PairKind::Renamed, &removed[j].path, &added[i].path, score));
+        }
+    }
+
+    if copies {
+        // A removed file can be both renamed and copied
+        let sources = modified.iter().chain(removed)
+            .map(|file| Ok((&file.path, read_old(&file.path)?)))
+            .collect::<Result<Vec<_>>>()?;
+        for (i, new) in new_contents.iter().filter(|(i, _)| !paired[*i]) {
+            let best = sources.iter()
+                .filter_map(|(path, old)| similarity_above(old, new, threshold).map(|score| (score, *path)))
+                .fold(None, |best: Option<(u8, &RootRelativePath)>, (score, path)| match best {
+                    Some((best_score, _)) if best_score >= score => best,
+                    _ => Some((score, path))
+                });
+            if let Some((score, source)) = best {
+                pairs.push(pair(PairKind::Copied, source, &added[*i].path, score));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.to.cmp(&b.to));
+    Ok(pairs)
+}
+
+// The share of the larger version's bytes that are in lines both versions have, in percent. Only identical files
+// reach 100
+pub(super) fn similarity(old: &[u8], new: &[u8]) -> u8 {
+    let larger = old.len().max(new.len());
+    if larger == 0 {
+        return 100;
+    }
+    let (old_lines, new_lines) = (diff::split_lines(old), diff::split_lines(new));
+    let common: usize = diff::diff_lines(&old_lines, &new_lines).iter()
+        .filter_map(|edit| match *edit {
+            Edit::Equal(o, _) => Some(old_lines[o].len()),
+            _ => None
+        })
+        .sum();
+    (common * 100 / larger) as u8
+}
+
+// Files whose sizes are too far apart can't be similar enough, so they aren't diffed. Empty files aren't similar to
+// anything
+fn similarity_above(old: &[u8], new: &[u8], threshold: u8) -> Option<u8> {
+    let (smaller, larger) = (old.len().min(new.len()), old.len().max(new.len()));
+    if smaller == 0 || smaller * 100 < larger * threshold as usize {
+        return None;
+    }
+    Some(similarity(old, new)).filter(|score| *score >= threshold)
+}
+
+impl Pair {
+    pub fn display(&self) -> String {
+        let marker = match self.kind {
+            PairKind::Renamed => "R",
+            PairKind::Copied => "C"
+        };
+        format!("{} {} -> {} ({}%)", marker, self.from.display(), self.to.display(), self.similarity)
+    }
+}
@@ -1,17 +1,31 @@
 mod commit_creation;
+mod interactive_add;
 mod path_processing;
 mod branching;
 pub mod checkout;
 mod ignored_files;
+mod restore;
+mod tagging;
+mod ref_logging;
+mod revision;
+pub mod sparse_checkout;
+pub mod worktrees;
+pub mod file_monitoring;
+pub mod change_reporting;
 
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::env;
+use std::num::NonZeroUsize;
+use std::thread;
 use crate::project::head::Head;
 use ignored_files::IgnoredFile;
+use super::attributes::Attributes;
 use super::commit::Commit;
 use super::paths::AbsolutePath;
 use super::staging_area::StagingArea;
+use super::pathspec::Pathspec;
+use super::stat_cache::StatCache;
 use super::error::{Result, GustError};
 use super::storable::ProjectStorable;
 
@@ -19,21 +33,42 @@ pub struct Root {
     path: RootPath,
     head: Head,
     staging_area: StagingArea,
-    ignored_files: Vec<IgnoredFile>
+    ignored_files: Vec<IgnoredFile>,
+    // None when sparse checkout is disabled
+    sparse_patterns: Option<Pathspec>,
+    attributes: Attributes,
+    stat_cache: StatCache,
+    // Threads used to scan folders and hash files
+    jobs: usize
 }
 
 impl Root {
-    pub fn new() -> Result<Root> {
-        let path = find_project_root()?;
+    // Without a jobs count, one thread per CPU is used
+    pub fn new(jobs: Option<NonZeroUsize>) -> Result<Root> {
+        let mut root = Self::open(find_project_root()?)?;
+        if let Some(jobs) = jobs {
+            root.jobs = jobs.get();
+        }
+        Ok(root)
+    }
+
+    fn open(path: RootPath) -> Result<Root> {
         let head = Head::create(path.clone())?;
         let staging_area = StagingArea::create(path.clone())?;
         let ignored_files = Self::read_ignored(&path)?;
+        let sparse_patterns = Self::read_sparse_patterns(&path)?;
+        let attributes = Attributes::read(&path)?;
+        let stat_cache = StatCache::new(path.clone(), true)?;
 
         Ok(Root {
             path,
             head,
             staging_area,
-            ignored_files
+            ignored_files,
+            sparse_patterns,
+            attributes,
+            stat_cache,
+            jobs: thread::available_parallelism().map_or(1, NonZeroUsize::get)
         })
     }
 
@@ -43,6 +78,8 @@ impl Root {
         fs::create_dir("./.gust/blobs")?;
         fs::create_dir("./.gust/commits")?;
         fs::create_dir("./.gust/branches")?;
+        fs::create_dir("./.gust/tags")?;
+        fs::create_dir("./.gust/tag_objects")?;
         Ok(())
     }
 
@@ -57,10 +94,14 @@ fn find_project_root() ->  Result<RootPath> { // Size for Path needs to be known
     let mut path = env::current_dir()?;
     loop {
         path.push(".gust"); // Check if path/.gust exists
-        if path.exists() {
+        if path.is_dir() {
             path.pop(); // Remove .gust
-            // Doesn't use any constructors because this function should be the only one able to create a ProjectRootPath
-            return Ok(RootPath(path));
+            return Ok(RootPath::main(path));
+        } else if path.is_file() {
+            // Linked worktrees have a .gust file that points to their state inside the main project's .gust
+            let gust_dir = worktrees::read_gust_link(&path)?;
+            path.pop();
+            return Ok(RootPath::linked(path, gust_dir));
         } else {
             // Go to the parent, pop twice because the path is now "parent/folder/.gust"
             path.pop();
@@ -71,22 +112,45 @@ fn find_project_root() ->  Result<RootPath> { // Size for Path needs to be known
     }
 }
 
+// Only find_project_root and the worktree commands create RootPaths
 #[derive(Clone)]
-pub struct RootPath(PathBuf);
+pub struct RootPath {
+    path: PathBuf,
+    // This working tree's HEAD, staging area and other state that isn't shared
+    gust_dir: PathBuf,
+    // Objects, branches and tags. It's the same as gust_dir except in linked worktrees
+    common_dir: PathBuf
+}
 impl RootPath {
+    fn main(path: PathBuf) -> Self {
+        let gust_dir = path.join(".gust");
+        Self { path, common_dir: gust_dir.clone(), gust_dir }
+    }
+    // Linked worktrees keep their state in <common_dir>/worktrees/<name>
+    fn linked(path: PathBuf, gust_dir: PathBuf) -> Self {
+        let common_dir = gust_dir.parent().and_then(Path::parent).unwrap_or(&gust_dir).to_path_buf();
+        Self { path, gust_dir, common_dir }
+    }
     pub(super) fn join<T: AsRef<Path>>(&self, path: T) -> AbsolutePath {
-        AbsolutePath::from_absolute_path(&self.0.join(path))
+        AbsolutePath::from_absolute_path(&self.path.join(path))
+    }
+    pub(super) fn gust_join<T: AsRef<Path>>(&self, path: T) -> AbsolutePath {
+        AbsolutePath::from_absolute_path(&self.gust_dir.join(path))
+    }
+    pub(super) fn common_join<T: AsRef<Path>>(&self, path: T) -> AbsolutePath {
+        AbsolutePath::from_absolute_path(&self.common_dir.join(path))
     }
     pub(super) fn unsafe_join<T: AsRef<Path>>(&self, path: T) -> Result<AbsolutePath> {
-        let result = AbsolutePath::from_absolute_path(&self.0.join(path));
+        let result = AbsolutePath::from_absolute_path(&self.path.join(path));
         if !result.as_path().exists() {
             Err(GustError::User(format!("Path {} does not exist", result.as_path().display())))
         } else {
             Ok(result)
         }
     }
-    pub(super) fn as_path(&self) -> &Path { self.0.as_path() }
+    pub(super) fn as_path(&self) -> &Path { self.path.as_path() }
+    pub(super) fn gust_dir(&self) -> &Path { self.gust_dir.as_path() }
     pub(super) fn is_inside_root(&self, path: &AbsolutePath) -> bool {
-        path.as_path().starts_with(&self.0.as_path())
+        path.as_path().starts_with(self.path.as_path())
     }
 }
\ No newline at end of file
@@ -1,43 +1,113 @@
 use super::Root;
 use super::Head;
-use crate::project::error::Result;
+use crate::project::error::{GustError, Result};
 use crate::project::branch::{Branch, BranchTrait};
+use crate::project::ref_name::{check_ref_lookup, list_ref_names, remove_ref_file, validate_ref_name};
+use crate::project::reflog::Reflog;
 use crate::project::storable::{ContainsStorePath, ProjectStorable};
-use std::fs;
+use std::collections::HashSet;
 
 impl Root {
-    pub fn branch(&mut self, branch_name: &Option<String>) -> Result<()> {
-        if let Some(name) = branch_name {
-            self.create_branch(name)
-        } else {
-            self.display_branches()
+    pub fn branch(&mut self, branch_name: &Option<String>, start_point: &Option<String>, delete: bool, force_delete: bool, rename: &Option<Vec<String>>, verbosity: u8) -> Result<()> {
+        if let Some(names) = rename {
+            // clap makes sure exactly two names are passed
+            return self.rename_branch(&names[0], &names[1]);
         }
+        match branch_name {
+            Some(name) if delete || force_delete => self.delete_branch(name, force_delete),
+            Some(name) => self.create_branch(name, start_point),
+            None if delete || force_delete => Err(GustError::User("Specify the branch to delete".into())),
+            None => self.display_branches(verbosity)
+        }
+    }
+
+    // Passing None removes the upstream. If no branch is passed, the current one is used
+    pub fn set_upstream(&mut self, branch_name: &Option<String>, upstream: Option<&str>) -> Result<()> {
+        let name = match (branch_name, &self.head) {
+            (Some(name), _) => name.clone(),
+            (None, Head::Attached(branch)) => branch.name.clone(),
+            (None, Head::Detached(_)) => return Err(GustError::User("HEAD is detached. Specify the branch to set the upstream of".into()))
+        };
+        if let Some(upstream) = upstream {
+            if upstream == name {
+                return Err(GustError::User(format!("Branch {} can't be its own upstream", name)));
+            }
+            self.load_existing_branch(upstream)?;
+        }
+
+        let mut branch = self.load_existing_branch(&name)?;
+        branch.upstream = upstream.map(|u| u.to_string());
+        branch.save()?;
+        if let Head::Attached(current) = &mut self.head && current.name == name {
+            current.upstream = branch.upstream;
+        }
+        Ok(())
+    }
+
+    // Describes how a branch relates to its upstream, e.g. "main: ahead 1, behind 2"
+    pub(super) fn upstream_status(&self, branch: &Branch) -> Result<Option<String>> {
+        let Some(upstream_name) = &branch.upstream else { return Ok(None) };
+        if !Branch::build_absolute_path(&(self.path.clone(), upstream_name.clone())).as_path().exists() {
+            return Ok(Some(format!("{}: gone", upstream_name)));
+        }
+        let upstream = Branch::load((self.path.clone(), upstream_name.clone()))?;
+        let (ahead, behind) = branch.ahead_behind(&upstream);
+        Ok(Some(format!("{}: ahead {}, behind {}", upstream_name, ahead, behind)))
     }
 
-    fn display_branches(&self) -> Result<()> {
-        let branches_path = self.path.join(".gust/branches");
+    fn display_branches(&self, verbosity: u8) -> Result<()> {
         let current_branch_name = match &self.head {
             Head::Attached(branch) => &branch.name,
             Head::Detached(branch) => {
-                println!("{}", format!("* HEAD attached at {}", branch.passed_hash));
+                println!("* HEAD attached at {}", branch.passed_hash);
                 ""
             }
         };
 
-        for branch_file in fs::read_dir(branches_path.as_path())? {
-            let branch_name = branch_file?.path().file_stem().unwrap().to_str().unwrap().to_string();
-            if branch_name != "DETACHED_HEAD" {
-                if branch_name != current_branch_name {
-                    println!("{}", branch_name);
-                } else {
-                    println!("* {}", branch_name);
-                }
+        for branch_name in self.branch_names()? {
+            let marker = if branch_name != current_branch_name { "" } else { "* " };
+            if verbosity == 0 {
+                println!("{}{}", marker, branch_name);
+                continue;
+            }
+
+            // -v adds the latest commit, -vv also adds the upstream
+            let branch = Branch::load((self.path.clone(), branch_name.clone()))?;
+            let mut line = format!("{}{}", marker, branch_name);
+            if verbosity > 1 && let Some(upstream) = self.upstream_status(&branch)? {
+                line.push_str(&format!(" [{}]", upstream));
             }
+            if let Some(commit) = branch.get_last_commit_ref() {
+                line.push_str(&format!(" {}", commit.display_short()));
+            }
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    pub(super) fn branch_names(&self) -> Result<Vec<String>> {
+        let mut names = list_ref_names(self.path.common_join("branches").as_path())?;
+        names.retain(|name| name != "DETACHED_HEAD");
+        Ok(names)
+    }
+
+    fn check_new_branch_name(&self, branch_name: &str) -> Result<()> {
+        validate_ref_name(branch_name, "branch")?;
+        if Branch::build_absolute_path(&(self.path.clone(), branch_name.to_string())).as_path().exists() {
+            return Err(GustError::User(format!("Branch {} already exists", branch_name)));
         }
         Ok(())
     }
 
-    fn create_branch(&mut self, branch_name: &str) -> Result<()> {
+    pub(super) fn create_branch(&mut self, branch_name: &str, start_point: &Option<String>) -> Result<()> {
+        self.check_new_branch_name(branch_name)?;
+        if let Some(start_point) = start_point {
+            let revision = self.resolve_revision(start_point)?;
+            let new_commit = revision.tip().map(|c| c.get_commit_id().to_string());
+            Branch::new_from_tree(revision.history, &self.path, branch_name).save()?;
+            return self.log_branch_update(branch_name, None, new_commit, &format!("branch: Created from {}", start_point));
+        }
+
         match &self.head {
             Head::Attached(branch) => {
                 let current_head_commit = branch.get_last_commit_ref();
@@ -54,6 +124,84 @@ impl Root {
                 new_branch.save()?;
             }
         };
+        let operation = format!("branch: Created from {}", self.head_name());
+        self.log_branch_update(branch_name, None, self.head_commit_id()?, &operation)
+    }
+
+    // Orphan branches start with no history, so checking them out leaves an empty tree
+    pub(super) fn create_orphan_branch(&mut self, branch_name: &str) -> Result<()> {
+        self.check_new_branch_name(branch_name)?;
+        Branch::create((self.path.clone(), branch_name.to_string()))?;
+        self.log_branch_update(branch_name, None, None, "branch: Created orphan")
+    }
+
+    pub(super) fn load_existing_branch(&self, name: &str) -> Result<Branch> {
+        check_ref_lookup(name, "branch")?;
+        if !Branch::build_absolute_path(&(self.path.clone(), name.to_string())).as_path().exists() {
+            return Err(GustError::User(format!("Branch {} not found", name)));
+        }
+        Branch::load((self.path.clone(), name.to_string()))
+    }
+
+    fn delete_branch(&mut self, name: &str, force: bool) -> Result<()> {
+        if let Head::Attached(current) = &self.head && current.name == name {
+            return Err(GustError::User(format!("Cannot delete branch {} because it's checked out", name)));
+        }
+        self.ensure_not_checked_out_elsewhere(name)?;
+        let branch = self.load_existing_branch(name)?;
+
+        if !force {
+            let mut reachable = HashSet::new();
+            for other_name in self.branch_names()?.into_iter().filter(|other| other != name) {
+                let other = Branch::load((self.path.clone(), other_name))?;
+                reachable.extend(other.commits().iter().map(|c| c.get_commit_id().to_string()));
+            }
+            if branch.commits().iter().any(|c| !reachable.contains(c.get_commit_id())) {
+                return Err(GustError::User(format!("Branch {} has commits that aren't on any other branch. Use 'gust branch -D {}' to delete it anyway", name, name)));
+            }
+        }
+
+        remove_ref_file(branch.get_absolute_path().as_path(), self.path.common_join("branches").as_path())?;
+        // The reflog is kept so the commits can still be found through it
+        let old_commit = branch.get_last_commit_ref().map(|c| c.get_commit_id().to_string());
+        self.log_branch_update(name, old_commit, None, "branch: deleted")
+    }
+
+    fn rename_branch(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        let branch = self.load_existing_branch(old_name)?;
+        // Only this worktree's HEAD is updated after the rename
+        self.ensure_not_checked_out_elsewhere(old_name)?;
+        validate_ref_name(new_name, "branch")?;
+        if Branch::build_absolute_path(&(self.path.clone(), new_name.to_string())).as_path().exists() {
+            return Err(GustError::User(format!("Branch {} already exists", new_name)));
+        }
+
+        let mut renamed = Branch::new_from_tree(branch.commits().clone(), &self.path, new_name);
+        renamed.upstream = branch.upstream.clone();
+        renamed.save()?;
+        remove_ref_file(branch.get_absolute_path().as_path(), self.path.common_join("branches").as_path())?;
+        let tip = branch.get_last_commit_ref().map(|c| c.get_commit_id().to_string());
+        Reflog::for_ref(&self.path, old_name).rename(&self.path, new_name)?
+            .append(tip.clone(), tip, &format!("branch: renamed {} to {}", old_name, new_name))?;
+
+        // Upstreams are stored by name too, so branches tracking the old name would show it as gone
+        for other_name in self.branch_names()?.into_iter().filter(|other| other != new_name) {
+            let mut other = Branch::load((self.path.clone(), other_name))?;
+            if other.upstream.as_deref() == Some(old_name) {
+                other.upstream = Some(new_name.to_string());
+                other.save()?;
+            }
+        }
+        if let Head::Attached(current) = &mut self.head && current.upstream.as_deref() == Some(old_name) {
+            current.upstream = Some(new_name.to_string());
+        }
+
+        // HEAD stores the branch by name, so it has to follow the rename
+        if let Head::Attached(current) = &self.head && current.name == old_name {
+            let new_head = Head::Attached(renamed);
+            new_head.save_to_path(&Head::build_absolute_path(&self.path))?;
+            self.head = new_head;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
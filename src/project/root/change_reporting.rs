@@ -0,0 +1,338 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use clap::{value_parser, Args};
+use super::Root;
+use crate::project::commit::{Commit, CommitRef};
+use crate::project::diff;
+use crate::project::error::Result;
+use crate::project::paths::RootRelativePath;
+use crate::project::renames::{self, Candidate, Candidates, Pair, PairKind};
+use crate::project::staging_area::ChangeType;
+use crate::project::storable::ProjectStorable;
+use crate::project::tracked_file::{blob_content, FileMode, TrackedFile};
+
+// Shared by the commands that report changes. Identical files are always paired, these add files that only look alike
+#[derive(Args)]
+pub(crate) struct RenameOptions {
+    // Also pairs files whose content is at least this similar, in percent. 50 when no value is given
+    #[arg(short = 'M', long, value_name = "PERCENT", num_args = 0..=1, require_equals = true, default_missing_value = "50",
+        value_parser = value_parser!(u8).range(..=100))]
+    pub find_renames: Option<u8>,
+    // Also reports new files that are copies of other files
+    #[arg(short = 'C', long)]
+    pub find_copies: bool
+}
+
+const MAX_STAT_WIDTH: usize = 40;
+
+type Tree = HashMap<RootRelativePath, TrackedFile>;
+
+// The new side of a comparison. The old side is always a tree
+pub(super) enum NewSide {
+    Tree(Tree),
+    WorkingTree
+}
+
+// The changes between two versions, and the renames and copies found among them
+pub(super) struct Comparison {
+    old: Tree,
+    new: NewSide,
+    changes: BTreeMap<RootRelativePath, ChangeType>,
+    pairs: Vec<Pair>
+}
+
+// A change to report. A rename or a copy is reported in place of the new file, and the removal a rename came from
+// isn't reported on its own
+pub(super) struct Entry<'a> {
+    pub old_path: &'a RootRelativePath,
+    pub new_path: &'a RootRelativePath,
+    pub change: &'a ChangeType,
+    pub pair: Option<&'a Pair>
+}
+
+impl Root {
+    // Without revisions, the working tree is compared with the staging area, or the staging area with HEAD when staged.
+    // A single revision is compared with the working tree
+    pub fn diff(&self, revisions: &[String], staged: bool, options: &RenameOptions) -> Result<()> {
+        let head_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        let comparison = match revisions {
+            [from, to] => {
+                let (old, new) = (self.revision_tree(from)?, self.revision_tree(to)?);
+                let changes = tree_changes(&old, &new);
+                self.compare(old, NewSide::Tree(new), changes, options)?
+            },
+            [revision] => {
+                let old = self.revision_tree(revision)?;
+                let changes = self.working_tree_changes(&old, &self.index_tree(&head_tree))?;
+                self.compare(old, NewSide::WorkingTree, changes, options)?
+            },
+            _ if staged => {
+                let index_tree = self.index_tree(&head_tree);
+                let changes = self.staging_area.get_files().into_iter().collect();
+                self.compare(head_tree, NewSide::Tree(index_tree), changes, options)?
+            },
+            _ => {
+                let changes = self.unstaged_changes()?;
+                self.compare(self.index_tree(&head_tree), NewSide::WorkingTree, changes, options)?
+            }
+        };
+        self.stat_cache.save_if_changed()?;
+        for entry in comparison.entries() {
+            self.print_file_diff(&comparison, &entry)?;
+        }
+        Ok(())
+    }
+
+    // "M file | 3 ++-" for every file the commit changed compared with its parent, then the totals
+    pub(super) fn print_stat(&self, parent: Option<&CommitRef>, commit: &CommitRef, options: &RenameOptions) -> Result<()> {
+        let old = match parent {
+            Some(parent) => Commit::from_commit_ref(parent, &self.path)?.copy_tree(),
+            None => HashMap::new()
+        };
+        let new = Commit::from_commit_ref(commit, &self.path)?.copy_tree();
+        let changes = tree_changes(&old, &new);
+        let comparison = self.compare(old, NewSide::Tree(new), changes, options)?;
+
+        // Binary files don't have line counts
+        let mut rows: Vec<(String, Option<(usize, usize)>)> = Vec::new();
+        for entry in comparison.entries() {
+            let old = self.old_content(&comparison.old, entry.old_path)?;
+            let new = self.new_content(&comparison.new, entry.new_path)?;
+            rows.push((entry.label(), (!is_binary(&old) && !is_binary(&new)).then(|| line_counts(&old, &new))));
+        }
+        let width = rows.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0);
+        let most = rows.iter().filter_map(|(_, counts)| counts.map(|(added, deleted)| added + deleted)).max().unwrap_or(0);
+        let scale = |lines: usize| if most <= MAX_STAT_WIDTH { lines } else { (lines * MAX_STAT_WIDTH).div_ceil(most) };
+        for (label, counts) in &rows {
+            let graph = match counts {
+                Some((added, deleted)) => format!("{} {}{}", added + deleted, "+".repeat(scale(*added)), "-".repeat(scale(*deleted))),
+                None => "Bin".into()
+            };
+            println!("  {:<width$} | {}", label, graph.trim_end());
+        }
+        let (added, deleted) = rows.iter().filter_map(|(_, counts)| *counts).fold((0, 0), |(a, d), (added, deleted)| (a + added, d + deleted));
+        let plural = |count: usize, word: &str| format!("{} {}{}", count, word, if count == 1 { "" } else { "s" });
+        println!("  {} changed, {}(+), {}(-)\n", plural(rows.len(), "file"), plural(added, "insertion"), plural(deleted, "deletion"));
+        Ok(())
+    }
+
+    // Renames staged with mv are reported with their similarity like the ones that are found
+    pub(super) fn compare(&self, old: Tree, new: NewSide, changes: BTreeMap<RootRelativePath, ChangeType>, options: &RenameOptions) -> Result<Comparison> {
+        let mut pairs = Vec::new();
+        for (file, change) in &changes {
+            let ChangeType::Renamed { from } = change else { continue; };
+            let similarity = match (old.get(from), self.new_blob_id(&new, file)?) {
+                (Some(old_file), Some(blob_id)) if old_file.get_blob_id() != blob_id => {
+                    renames::similarity(&old_file.read_blob(&self.path)?, &self.new_content(&new, file)?)
+                },
+                _ => 100
+            };
+            pairs.push(Pair { kind: PairKind::Renamed, from: from.clone(), to: file.clone(), similarity });
+        }
+
+        let old_candidate = |path: &RootRelativePath| old.get(path).map(|f| Candidate { path: path.clone(), blob_id: f.get_blob_id().to_string() });
+        let with_change = |wanted: fn(&ChangeType) -> bool| changes.iter().filter(move |(_, change)| wanted(change)).map(|(file, _)| file);
+        let mut added = Vec::new();
+        for file in with_change(|change| matches!(change, ChangeType::Added)) {
+            if let Some(blob_id) = self.new_blob_id(&new, file)? {
+                added.push(Candidate { path: file.clone(), blob_id });
+            }
+        }
+        if !added.is_empty() {
+            let mut kept: Vec<Candidate> = Vec::new();
+            if options.find_copies {
+                kept = old.keys().filter(|file| !matches!(changes.get(file), Some(ChangeType::Removed))).filter_map(old_candidate).collect();
+                kept.sort_by(|a, b| a.path.cmp(&b.path));
+            }
+            let candidates = Candidates {
+                removed: with_change(|change| matches!(change, ChangeType::Removed)).filter_map(old_candidate).collect(),
+                added,
+                kept,
+                modified: with_change(|change| matches!(change, ChangeType::Modified)).filter_map(old_candidate).collect()
+            };
+            let read_old = |file: &RootRelativePath| self.old_content(&old, file);
+            let read_new = |file: &RootRelativePath| self.new_content(&new, file);
+            pairs.extend(renames::find_pairs(&candidates, options.find_renames, options.find_copies, read_old, read_new)?);
+        }
+        pairs.sort_by(|a, b| a.to.cmp(&b.to));
+        Ok(Comparison { old, new, changes, pairs })
+    }
+
+    // HEAD's tree with the staged changes, what the next commit would have. Entries staged by older versions of gust
+    // don't have a snapshot, so HEAD's version is kept for them
+    pub(super) fn index_tree(&self, head_tree: &Tree) -> Tree {
+        let mut tree = head_tree.clone();
+        for from in self.staging_area.rename_sources() {
+            tree.remove(&from);
+        }
+        for (file, staged) in &self.staging_area {
+            match (&staged.change, &staged.snapshot) {
+                (ChangeType::Removed, _) => { tree.remove(file); },
+                (_, Some(snapshot)) => { tree.insert(file.clone(), snapshot.clone()); },
+                _ => {}
+            }
+        }
+        tree
+    }
+
+    // The files of the old tree or the staging area as they are on disk. Untracked files aren't compared, and paths
+    // outside the sparse checkout aren't on disk on purpose
+    fn working_tree_changes(&self, old: &Tree, tracked: &Tree) -> Result<BTreeMap<RootRelativePath, ChangeType>> {
+        let files: BTreeSet<&RootRelativePath> = old.keys().chain(tracked.keys()).filter(|file| self.is_materialized(file)).collect();
+        let mut changes = BTreeMap::new();
+        for file in files {
+            let current = self.new_blob_id(&NewSide::WorkingTree, file)?.zip(self.new_mode(&NewSide::WorkingTree, file)?);
+            let change = match (old.get(file), current) {
+                (None, Some(_)) => ChangeType::Added,
+                (Some(_), None) => ChangeType::Removed,
+                (Some(old), Some((blob_id, _))) if old.get_blob_id() != blob_id => ChangeType::Modified,
+                (Some(old), Some((_, mode))) if old.mode != mode => ChangeType::ModeChanged,
+                _ => continue
+            };
+            changes.insert(file.clone(), change);
+        }
+        Ok(changes)
+    }
+
+    fn revision_tree(&self, revision: &str) -> Result<Tree> {
+        let hash = self.resolve_commit_id(revision)?;
+        Ok(Commit::load((self.path.clone(), hash))?.copy_tree())
+    }
+
+    fn print_file_diff(&self, comparison: &Comparison, entry: &Entry) -> Result<()> {
+        let (old_name, new_name) = (format!("a/{}", entry.old_path.display()), format!("b/{}", entry.new_path.display()));
+        println!("diff --gust {} {}", old_name, new_name);
+        let old_mode = comparison.old.get(entry.old_path).map(|f| f.mode);
+        let new_mode = self.new_mode(&comparison.new, entry.new_path)?;
+        match (entry.pair, old_mode, new_mode) {
+            (None, None, Some(mode)) => println!("new file, {}", mode_name(mode)),
+            (None, Some(mode), None) => println!("deleted file, {}", mode_name(mode)),
+            (_, Some(old_mode), Some(new_mode)) if old_mode != new_mode => {
+                println!("old mode {}\nnew mode {}", mode_name(old_mode), mode_name(new_mode));
+            },
+            _ => {}
+        }
+        if let Some(pair) = entry.pair {
+            let kind = if pair.kind == PairKind::Renamed { "rename" } else { "copy" };
+            println!("similarity index {}%\n{1} from {2}\n{1} to {3}", pair.similarity, kind, pair.from.display(), pair.to.display());
+        }
+
+        let old = self.old_content(&comparison.old, entry.old_path)?;
+        let new = self.new_content(&comparison.new, entry.new_path)?;
+        if old == new {
+            return Ok(());
+        }
+        let old_name = if old_mode.is_some() { old_name } else { "/dev/null".into() };
+        let new_name = if new_mode.is_some() { new_name } else { "/dev/null".into() };
+        if is_binary(&old) || is_binary(&new) {
+            println!("Binary files {} and {} differ", old_name, new_name);
+            return Ok(());
+        }
+        println!("--- {}\n+++ {}", old_name, new_name);
+        let (old_lines, new_lines) = (diff::split_lines(&old), diff::split_lines(&new));
+        let edits = diff::diff_lines(&old_lines, &new_lines);
+        for hunk in diff::hunks(&edits, diff::CONTEXT_LINES) {
+            diff::print_hunk(&edits, &hunk, &old_lines, &new_lines);
+        }
+        Ok(())
+    }
+
+    // Files missing from a side are empty
+    fn old_content(&self, old: &Tree, file: &RootRelativePath) -> Result<Vec<u8>> {
+        old.get(file).map_or(Ok(Vec::new()), |f| f.read_blob(&self.path))
+    }
+
+    fn new_content(&self, new: &NewSide, file: &RootRelativePath) -> Result<Vec<u8>> {
+        match new {
+            NewSide::Tree(tree) => tree.get(file).map_or(Ok(Vec::new()), |f| f.read_blob(&self.path)),
+            NewSide::WorkingTree => {
+                let absolute_path = self.path.join(file.as_path());
+                if absolute_path.is_file() { blob_content(absolute_path.as_path(), self.attributes.conversion_for(file)) } else { Ok(Vec::new()) }
+            }
+        }
+    }
+
+    // Working tree files are hashed through the stat cache, so files that didn't change aren't read again
+    fn new_blob_id(&self, new: &NewSide, file: &RootRelativePath) -> Result<Option<String>> {
+        match new {
+            NewSide::Tree(tree) => Ok(tree.get(file).map(|f| f.get_blob_id().to_string())),
+            NewSide::WorkingTree => {
+                let absolute_path = self.path.join(file.as_path());
+                if !absolute_path.is_file() {
+                    return Ok(None);
+                }
+                Ok(Some(self.stat_cache.blob_id(file, &absolute_path, self.attributes.conversion_for(file))?))
+            }
+        }
+    }
+
+    fn new_mode(&self, new: &NewSide, file: &RootRelativePath) -> Result<Option<FileMode>> {
+        match new {
+            NewSide::Tree(tree) => Ok(tree.get(file).map(|f| f.mode)),
+            NewSide::WorkingTree => {
+                let absolute_path = self.path.join(file.as_path());
+                if absolute_path.is_file() { Ok(Some(FileMode::from_file(&absolute_path)?)) } else { Ok(None) }
+            }
+        }
+    }
+}
+
+impl Comparison {
+    // Sorted by the new path
+    pub fn entries(&self) -> Vec<Entry<'_>> {
+        let renamed_from: HashSet<&RootRelativePath> = self.pairs.iter().filter(|p| p.kind == PairKind::Renamed).map(|p| &p.from).collect();
+        let by_target: HashMap<&RootRelativePath, &Pair> = self.pairs.iter().map(|p| (&p.to, p)).collect();
+        self.changes.iter()
+            .filter(|(file, change)| !(matches!(change, ChangeType::Removed) && renamed_from.contains(file)))
+            .map(|(file, change)| match by_target.get(file) {
+                Some(pair) => Entry { old_path: &pair.from, new_path: file, change, pair: Some(pair) },
+                None => Entry { old_path: file, new_path: file, change, pair: None }
+            })
+            .collect()
+    }
+}
+
+impl Entry<'_> {
+    pub fn label(&self) -> String {
+        match self.pair {
+            Some(pair) => pair.display(),
+            None => format!("{} {}", self.change.display(), self.new_path.display())
+        }
+    }
+}
+
+// The changes that turn one tree into the other
+pub(super) fn tree_changes(old: &Tree, new: &Tree) -> BTreeMap<RootRelativePath, ChangeType> {
+    let mut changes = BTreeMap::new();
+    for (file, old_file) in old {
+        match new.get(file) {
+            None => { changes.insert(file.clone(), ChangeType::Removed); },
+            Some(new_file) if new_file.get_blob_id() != old_file.get_blob_id() => { changes.insert(file.clone(), ChangeType::Modified); },
+            Some(new_file) if new_file.mode != old_file.mode => { changes.insert(file.clone(), ChangeType::ModeChanged); },
+            _ => {}
+        }
+    }
+    for file in new.keys().filter(|file| !old.contains_key(file)) {
+        changes.insert(file.clone(), ChangeType::Added);
+    }
+    changes
+}
+
+// Same check as add -p, a NUL byte means the file isn't text
+fn is_binary(content: &[u8]) -> bool { content.contains(&0) }
+
+fn line_counts(old: &[u8], new: &[u8]) -> (usize, usize) {
+    let (old_lines, new_lines) = (diff::split_lines(old), diff::split_lines(new));
+    diff::diff_lines(&old_lines, &new_lines).iter().fold((0, 0), |(added, deleted), edit| match edit {
+        diff::Edit::Insert(_) => (added + 1, deleted),
+        diff::Edit::Delete(_) => (added, deleted + 1),
+        diff::Edit::Equal(..) => (added, deleted)
+    })
+}
+
+fn mode_name(mode: FileMode) -> &'static str {
+    match mode {
+        FileMode::Regular => "regular",
+        FileMode::Executable => "executable",
+        FileMode::Symlink => "symlink"
+    }
+}
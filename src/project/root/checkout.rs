@@ -3,116 +3,117 @@ use clap::ValueEnum;
 use crate::project::branch::{Branch, BranchTrait, DetachedBranch};
 use crate::project::commit::{Commit, CommitRef};
 use super::Root;
+use super::revision::{RevisionError, RevisionKind};
 use crate::project::error::{GustError, Result as GustResult};
 use crate::project::head::Head;
-use crate::project::paths::RootRelativePath;
+use crate::project::paths::{remove_empty_parent_dirs, RootRelativePath};
+use crate::project::ref_name::remove_ref_file;
+use crate::project::staging_area::ChangeType;
 use crate::project::storable::{ContainsStorePath, ProjectStorable};
 use std::fs;
+use std::path::{Path, PathBuf};
 use crate::project::tracked_file::TrackedFile;
 
 #[derive(ValueEnum, Clone)]
 pub(crate) enum CheckoutMode {
     Branch,
     Commit,
+    Tag,
 }
 
-enum CommitCheckoutError {
-    CommitNotFound,
-    MultipleCommitsFound(Vec<String>),
-    NormalError(GustError)
-}
+impl Root {
+    // force discards uncommitted changes and overwrites untracked files that are in the way
+    pub fn checkout(&mut self, checkout_mode: &Option<CheckoutMode>, name: &str, force: bool) -> GustResult<()> {
+        if !force {
+            self.ensure_no_changes()?;
+        }
+
+        let kind = checkout_mode.as_ref().map(|mode| match mode {
+            CheckoutMode::Branch => RevisionKind::Branch,
+            CheckoutMode::Commit => RevisionKind::Commit,
+            CheckoutMode::Tag => RevisionKind::Tag,
+        });
+        let revision = match self.resolve_revision_of_kind(name, kind) {
+            Ok(revision) => revision,
+            Err(RevisionError::Ambiguous(_, candidates)) => return Err(GustError::User(format!(
+                "{} has the same name as more than one revision:\n  {}\nSpecify if you want to checkout a branch, a tag or a commit using --mode",
+                name, candidates.join("\n  ")
+            ))),
+            Err(e) => return Err(e.into())
+        };
 
-impl From<std::io::Error> for CommitCheckoutError {
-    fn from(value: std::io::Error) -> Self {
-        CommitCheckoutError::NormalError(GustError::Io(value))
+        match revision.branch {
+            Some(branch_name) => self.checkout_branch(&branch_name, force)?,
+            None => self.checkout_detached(revision.history, name, force)?
+        }
+        // The discarded changes include the staged ones, or they would be committed on top of the new HEAD
+        if force {
+            self.staging_area.clear()?;
+        }
+        Ok(())
     }
-}
-impl From<CommitCheckoutError> for GustError {
-    fn from(value: CommitCheckoutError) -> Self {
-        match value {
-            CommitCheckoutError::CommitNotFound => GustError::User("Commit not found".into()),
-            CommitCheckoutError::MultipleCommitsFound(commits) => GustError::User(format!("Multiple commits found\n{:?}", commits)),
-            CommitCheckoutError::NormalError(error) => error
+
+    // Unlike checkout, switch only accepts branches, and it can create the branch it switches to
+    pub fn switch(&mut self, name: &str, create: bool, orphan: bool, start_point: &Option<String>) -> GustResult<()> {
+        self.ensure_no_changes()?;
+        if orphan {
+            self.create_orphan_branch(name)?;
+        } else if create {
+            self.create_branch(name, start_point)?;
+        } else {
+            self.resolve_revision_of_kind(name, Some(RevisionKind::Branch))?;
         }
+
+        // A branch created for a switch that fails is deleted again. Like any deleted branch, its reflog keeps the
+        // creation and the deletion. Only the branch is undone: a checkout that fails while writing files leaves the
+        // files it already wrote, so the working tree may be partly switched
+        let result = self.checkout_branch(name, false);
+        if result.is_err() && (create || orphan) {
+            let branch = self.load_existing_branch(name)?;
+            remove_ref_file(branch.get_absolute_path().as_path(), self.path.common_join("branches").as_path())?;
+            let tip = branch.get_last_commit_ref().map(|c| c.get_commit_id().to_string());
+            self.log_branch_update(name, tip, None, "branch: deleted")?;
+        }
+        result
     }
-}
 
-impl Root {
-    pub fn checkout(&mut self, checkout_mode: &Option<CheckoutMode>, name: &str) -> GustResult<()> {
-        if !self.get_changed_files()?.is_empty() {
+    // Untracked files don't count as changes, checkout leaves them where they are
+    pub(super) fn ensure_no_changes(&self) -> GustResult<()> {
+        let has_changes = self.get_changed_files()?.into_iter()
+            .any(|(path, change)| !matches!(change, ChangeType::Added) || self.staging_area.contains(&path));
+        if has_changes {
             return Err(GustError::User(
                 "There are uncommitted changes in the project. Commit or stash them before checking out a branch".into()
             ));
         }
-
-        // TODO: Map the "file not found error" to "branch doesnt exist"
-        if let Some(mode) = checkout_mode {
-            match mode {
-                CheckoutMode::Branch => self.checkout_branch(name)?,
-                CheckoutMode::Commit => self.checkout_commit(name)?,
-            };
-        } else {
-            let branch_path = Branch::build_absolute_path(&(self.path.clone(), name.to_string()));
-            let commit_hash = self.get_full_commit_hash(name);
-            
-            return if branch_path.as_path().exists() {
-                match commit_hash {
-                    Ok(_) => Err(GustError::User("Branch has the same name as commit. Specify if you want to checkout a branch or a commit using --mode".into())),
-                    Err(e) => match e {
-                        CommitCheckoutError::CommitNotFound => self.checkout_branch(name),
-                        CommitCheckoutError::MultipleCommitsFound(found) => Err(GustError::User(format!("Branch name matches with multiple commits:\n{:?}\nSpecify if you want to checkout a branch or a commit using --mode", found))),
-                        CommitCheckoutError::NormalError(error) => Err(error)
-                    }
-                }
-            } else {
-                match commit_hash {
-                    Ok(_) => self.checkout_commit(name),
-                    Err(e) => Err(e.into())
-                }
-            }
-        }
         Ok(())
     }
 
-    fn checkout_commit(&mut self, partial_hash: &str) -> GustResult<()> {
-        let full_hash = self.get_full_commit_hash(partial_hash)?;
-
-        let commit = Commit::load((self.path.clone(), full_hash.clone()))?;
-        let commit_ref = CommitRef::new_from_existing(&commit, full_hash);
+    // passed_name is what the user typed, and it's shown as the detached HEAD's name
+    fn checkout_detached(&mut self, history: Vec<CommitRef>, passed_name: &str, force: bool) -> GustResult<()> {
+        let old_commit = self.head_commit_id()?;
+        let old_name = self.head_name().to_string();
+        let Some(tip) = history.last() else {
+            return Err(GustError::User(format!("{} doesn't have any commits", passed_name)));
+        };
+        let commit = Commit::from_commit_ref(tip, &self.path)?;
 
         // TODO: No hacer operaciones destructivas como handle_checkout en puntos donde puedan surgir errores
         // TODO: que hacer .save tambien se corra en los hijos
-        self.apply_changes_to_working_tree(commit.copy_tree())?;
+        self.apply_changes_to_working_tree(commit.copy_tree(), force)?;
         self.head.handle_checkout()?;
-        let detached_branch = DetachedBranch::new_from_commit_ref(commit_ref, &self.path, partial_hash)?;
+        let detached_branch = DetachedBranch::new_from_tree(history, &self.path, passed_name);
         detached_branch.save()?;
         let new_head = Head::Detached(detached_branch);
         new_head.save_to_path(&Head::build_absolute_path(&self.path))?;
         self.head = new_head;
-        Ok(())
-    }
-
-    fn get_full_commit_hash(&self, partial_hash: &str) -> Result<String, CommitCheckoutError> {
-        let commits = fs::read_dir(&self.path.join(".gust/commits/").as_path())?;
-        let mut found_commit_hashes= Vec::new();
-
-        for commit in commits {
-            let commit_name = commit?.path().file_stem().unwrap().to_str().unwrap().to_string();
-            if commit_name.starts_with(partial_hash) {
-                found_commit_hashes.push(commit_name);
-            }
-        }
-
-        if found_commit_hashes.len() == 0 {
-            Err(CommitCheckoutError::CommitNotFound)
-        } else if found_commit_hashes.len() == 1 {
-            Ok(found_commit_hashes[0].clone())
-        } else {
-            Err(CommitCheckoutError::MultipleCommitsFound(found_commit_hashes))
-        }
+        self.log_checkout(old_commit, &old_name)
     }
 
-    fn checkout_branch(&mut self, name: &str) -> GustResult<()> {
+    fn checkout_branch(&mut self, name: &str, force: bool) -> GustResult<()> {
+        self.ensure_not_checked_out_elsewhere(name)?;
+        let old_commit = self.head_commit_id()?;
+        let old_name = self.head_name().to_string();
         let dest_branch = Branch::load((self.path.clone(), name.into()))?;
         let dest_branch_latest = Commit::from_commit_ref_option(dest_branch.get_last_commit_ref(), &self.path)?;
         let tree = if let Some(commit) = dest_branch_latest {
@@ -121,31 +122,117 @@ impl Root {
             HashMap::new()
         };
 
-        self.apply_changes_to_working_tree(tree)?;
+        self.apply_changes_to_working_tree(tree, force)?;
 
         let new_head = Head::Attached(dest_branch);
         new_head.save_to_path(&Head::build_absolute_path(&self.path))?;
         self.head.handle_checkout()?;
         self.head = new_head;
-        Ok(())
+        self.log_checkout(old_commit, &old_name)
     }
 
-    fn apply_changes_to_working_tree(&self, target_tree: HashMap<RootRelativePath, TrackedFile>) -> GustResult<()> {
-        // Delete files not present in the tree
-        for absolute_path in self.scan_folder(&self.path.join("."))? {
-            let relative_path = RootRelativePath::new(&absolute_path, &self.path)?;
-            if !target_tree.contains_key(&relative_path) { // TODO: Check that the file is also not being ignored
+    // Only paths tracked by HEAD or by the target are touched, untracked and ignored files are left alone. Paths outside
+    // the sparse checkout patterns aren't written, and they're deleted if they were materialized before
+    pub(super) fn apply_changes_to_working_tree(&self, target_tree: HashMap<RootRelativePath, TrackedFile>, force: bool) -> GustResult<()> {
+        let current_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+
+        // Check before touching anything, so a refused checkout doesn't leave the tree half updated
+        if !force {
+            let mut overwritten: Vec<String> = target_tree.keys().filter(|path| self.is_materialized(path))
+                .flat_map(|path| self.untracked_files_in_the_way(path, &current_tree, &target_tree))
+                .collect();
+            if !overwritten.is_empty() {
+                overwritten.sort();
+                overwritten.dedup();
+                return Err(GustError::User(format!(
+                    "The following untracked files would be overwritten by checkout:\n  {}\nMove or remove them, or use --force",
+                    overwritten.join("\n  ")
+                )));
+            }
+        }
+
+        // Delete the files that HEAD tracks and the target doesn't, along with the folders they leave empty
+        for path in current_tree.keys().filter(|path| !target_tree.contains_key(*path) || !self.is_materialized(path)) {
+            let absolute_path = self.path.join(path.as_path());
+            if absolute_path.is_file() {
                 fs::remove_file(absolute_path.as_path())?;
+                remove_empty_parent_dirs(absolute_path.as_path(), self.path.as_path())?;
             }
         }
 
-        // Set the files with the tree's version of them
-        for (path, content) in target_tree {
-            let destination_path = &self.path.join(path.as_path());
-            let blob_path = self.path.join(&format!(".gust/blobs/{}", content.get_blob_id()));
-            fs::copy(blob_path.as_path(), destination_path.as_path())?;
+        // Only write the files whose content differs between both trees. Without uncommitted changes, the rest are
+        // already up to date. Forced checkouts can have local changes, so every file is written
+        for (path, content) in target_tree.into_iter().filter(|(path, _)| self.is_materialized(path)) {
+            let destination_path = self.path.join(path.as_path());
+            let unchanged = current_tree.get(&path)
+                .is_some_and(|current| current.get_blob_id() == content.get_blob_id() && current.mode == content.mode);
+            if !force && unchanged && destination_path.is_file() {
+                continue;
+            }
+
+            // Whatever is left in the way is untracked, and it was either checked above or the checkout is forced
+            if destination_path.is_dir() {
+                fs::remove_dir_all(destination_path.as_path())?;
+            }
+            for ancestor in path.as_path().ancestors().skip(1).filter(|a| !a.as_os_str().is_empty()) {
+                let absolute_ancestor = self.path.join(ancestor);
+                if absolute_ancestor.is_file() {
+                    fs::remove_file(absolute_ancestor.as_path())?;
+                }
+            }
+            if let Some(parent) = destination_path.as_path().parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let conversion = self.attributes.conversion_for(&path);
+            content.write_to(&destination_path, &self.path, conversion)?;
+            self.stat_cache.record(&path, &destination_path, conversion, content.get_blob_id())?;
         }
+        self.stat_cache.save_if_changed()?;
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    // Lists the files that would be lost by writing path. A file or folder can be replaced by the other, but only
+    // if everything in the way is tracked by HEAD and will be deleted by the checkout
+    fn untracked_files_in_the_way(&self, path: &RootRelativePath, current_tree: &HashMap<RootRelativePath, TrackedFile>, target_tree: &HashMap<RootRelativePath, TrackedFile>) -> Vec<String> {
+        let is_deleted = |relative: &Path| {
+            let relative = RootRelativePath::from_relative_path(relative);
+            current_tree.contains_key(&relative) && (!target_tree.contains_key(&relative) || !self.is_materialized(&relative))
+        };
+
+        let mut in_the_way = Vec::new();
+        let absolute_path = self.path.join(path.as_path());
+        if absolute_path.is_dir() {
+            for file in files_under(absolute_path.as_path()) {
+                let relative = file.strip_prefix(self.path.as_path()).unwrap();
+                if !is_deleted(relative) {
+                    in_the_way.push(relative.display().to_string());
+                }
+            }
+        } else if absolute_path.exists() && !current_tree.contains_key(path) {
+            in_the_way.push(path.display());
+        }
+
+        // A file where one of the parent folders has to be created
+        for ancestor in path.as_path().ancestors().skip(1).filter(|a| !a.as_os_str().is_empty()) {
+            if self.path.join(ancestor).is_file() && !is_deleted(ancestor) {
+                in_the_way.push(ancestor.display().to_string());
+            }
+        }
+        in_the_way
+    }
+}
+
+// Every file inside dir, including ignored ones
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            files.extend(files_under(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
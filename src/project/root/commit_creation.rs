@@ -1,58 +1,264 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 use crate::project::commit::{CommitMetadata, CommitRef};
 use crate::project::error::GustError;
-use crate::project::paths::{AbsolutePath, CliPath, RootRelativePath};
-use super::{Root, Result};
+use crate::project::paths::{remove_empty_parent_dirs, CliPath, RootRelativePath};
+use crate::project::pathspec::Pathspec;
+use crate::project::staging_area::ChangeType;
+use crate::project::tracked_file::{FileMode, TrackedFile};
+use super::{Root, Head, Result};
+use super::change_reporting::{Comparison, NewSide, RenameOptions};
 
 impl Root {
     // CLI commands
-    pub fn add(&mut self, paths: &Vec<PathBuf>) -> Result<()> {
-        // TODO: Use a trie for faster addition
-        // Checks that the user added the change either by passing the direct file or a parent directory
-        for (file, change) in self.get_changed_files()? {
-            let absolute_file = self.path.join(file.as_path());
-            for cli_path in paths {
-                let absolute_cli = AbsolutePath::try_from(CliPath::from(cli_path.as_path()))?;
-                if !self.is_path_ignored(&absolute_cli)? && (absolute_file == absolute_cli || absolute_file.as_path().starts_with(absolute_cli.as_path())) {
-                    self.staging_area.insert(file.clone(), change.clone())?;
-                }
+    // Without paths, -A and -u apply to the whole tree. -u only stages changes to files that are already tracked
+    pub fn add(&mut self, paths: &[PathBuf], all: bool, update: bool) -> Result<()> {
+        let pathspec = Pathspec::parse(paths, &self.path)?;
+        if pathspec.is_empty() && !all && !update {
+            return Err(GustError::User("Nothing specified, nothing added. Use 'gust add -A' to add every change".into()));
+        }
+        let changed_files = self.get_changed_files()?;
+        let head_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        let staged_files = self.staging_area.get_files();
+        pathspec.ensure_matches_any(changed_files.keys().chain(head_tree.keys()).chain(staged_files.keys()), &self.path)?;
+
+        let to_stage: Vec<(RootRelativePath, ChangeType)> = changed_files.iter()
+            .filter(|(file, change)| pathspec.matches(file) && !(update && matches!(change, ChangeType::Added)))
+            .map(|(file, change)| (file.clone(), change.clone()))
+            .collect();
+        // A staged file that is back to its committed version has nothing left to stage
+        let reverted: Vec<RootRelativePath> = staged_files.into_keys()
+            .filter(|file| pathspec.matches(file) && !changed_files.contains_key(file))
+            .collect();
+
+        for (file, change) in to_stage {
+            self.stage_file(file, change)?;
+        }
+        for file in reverted {
+            self.staging_area.remove(file)?;
+        }
+        self.stat_cache.save_if_changed()
+    }
+
+    // Stores the file's current content as a blob, so the commit gets the version that was added even if the file
+    // changes again before committing
+    pub(super) fn stage_file(&mut self, file: RootRelativePath, change: ChangeType) -> Result<()> {
+        if let ChangeType::Removed = change {
+            // A staged rename already takes the file out of the commit
+            if self.staging_area.rename_sources().contains(&file) {
+                return Ok(());
+            }
+            return self.staging_area.insert_removal(file);
+        }
+        // The new path of a renamed file isn't in HEAD, but adding it again keeps the rename
+        let change = match (change, self.staging_area.get_change(&file)) {
+            (ChangeType::Added, Some(renamed @ ChangeType::Renamed { .. })) => renamed.clone(),
+            (change, _) => change
+        };
+        let absolute_path = self.path.join(file.as_path());
+        let conversion = self.attributes.conversion_for(&file);
+        let snapshot = TrackedFile::new(&absolute_path, &self.path, conversion)?;
+        self.stat_cache.record(&file, &absolute_path, conversion, snapshot.get_blob_id())?;
+        self.staging_area.insert_snapshot(file, change, snapshot)
+    }
+
+    // Deletes tracked files and stages their removal. With cached, they stay on disk and become untracked. Files with
+    // changes that aren't committed are only deleted with force, since the changes would be lost
+    pub fn remove(&mut self, paths: &[PathBuf], cached: bool, force: bool) -> Result<()> {
+        let pathspec = Pathspec::parse(paths, &self.path)?;
+        let head_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        let staged_files = self.staging_area.get_files();
+        let tracked: BTreeSet<RootRelativePath> = self.tracked_files(&head_tree).into_iter().filter(|file| pathspec.matches(file)).collect();
+        pathspec.ensure_matches_tracked(tracked.iter())?;
+
+        if !cached && !force {
+            let changed_files = self.get_changed_files()?;
+            let has_changes = |file: &RootRelativePath| {
+                matches!(staged_files.get(file), Some(change) if !matches!(change, ChangeType::Removed))
+                    || matches!(changed_files.get(file), Some(ChangeType::Modified | ChangeType::ModeChanged))
+            };
+            if let Some(file) = tracked.iter().find(|file| has_changes(file)) {
+                return Err(GustError::User(format!("{} has changes that aren't committed. Use -f to remove it anyway, or --cached to keep it on disk", file.display())));
             }
         }
 
+        for file in tracked {
+            // A file that was only staged isn't in any commit, so there's no removal to stage. A renamed file's old
+            // path is removed instead
+            if let Some(ChangeType::Renamed { from }) = staged_files.get(&file) {
+                self.staging_area.insert_removal(from.clone())?;
+            }
+            if head_tree.contains_key(&file) {
+                self.staging_area.insert_removal(file.clone())?;
+            } else {
+                self.staging_area.remove(file.clone())?;
+            }
+            let absolute_path = self.path.join(file.as_path());
+            if !cached && absolute_path.is_file() {
+                fs::remove_file(absolute_path.as_path())?;
+                remove_empty_parent_dirs(absolute_path.as_path(), self.path.as_path())?;
+            }
+        }
         Ok(())
     }
 
-    pub fn remove(&mut self, paths: &Vec<PathBuf>) -> Result<()> {
-        self.process_path_list(paths, |root, path| {
-            let relative_path = RootRelativePath::new(&path, &root.path)?;
-            root.staging_area.remove(relative_path)?;
-            Ok(())
-        })
+    // Moves a file or folder and stages the rename of every tracked file in it. The staged content moves with the
+    // files, so changes that weren't staged stay unstaged at the new path
+    pub fn move_path(&mut self, source: &Path, destination: &Path) -> Result<()> {
+        let source = self.relative_cli_path(source)?;
+        let mut destination = self.relative_cli_path(destination)?;
+        let source_path = self.path.join(source.as_path());
+        // Moving into a folder keeps the name
+        if self.path.join(destination.as_path()).is_dir() && let Some(name) = source.as_path().file_name() {
+            destination = RootRelativePath::from_relative_path(&destination.as_path().join(name));
+        }
+        let destination_path = self.path.join(destination.as_path());
+        if !source_path.exists() {
+            return Err(GustError::User(format!("{} doesn't exist", source.display())));
+        }
+        if destination_path.exists() {
+            return Err(GustError::User(format!("{} already exists", destination.display())));
+        }
+        if destination.as_path().starts_with(source.as_path()) {
+            return Err(GustError::User(format!("Can't move {} inside itself", source.display())));
+        }
+        if destination_path.as_path().parent().is_some_and(|parent| !parent.is_dir()) {
+            return Err(GustError::User(format!("The folder of {} doesn't exist", destination.display())));
+        }
+
+        let head_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        let staged_files = self.staging_area.get_files();
+        let moved: Vec<RootRelativePath> = self.tracked_files(&head_tree).into_iter()
+            .filter(|file| file.as_path().starts_with(source.as_path()) && !matches!(staged_files.get(file), Some(ChangeType::Removed)))
+            .collect();
+        if moved.is_empty() {
+            return Err(GustError::User(format!("{} isn't tracked", source.display())));
+        }
+        fs::rename(source_path.as_path(), destination_path.as_path())?;
+
+        for file in moved {
+            let rest = file.as_path().strip_prefix(source.as_path()).unwrap_or(file.as_path());
+            let new_path = match rest.as_os_str().is_empty() {
+                true => destination.clone(),
+                false => RootRelativePath::from_relative_path(&destination.as_path().join(rest))
+            };
+            let snapshot = match (self.staging_area.get_snapshot(&file), head_tree.get(&file)) {
+                (Some(snapshot), _) | (None, Some(snapshot)) => snapshot.clone(),
+                // Staged by an older version of gust, so only the file on disk has the content
+                (None, None) => {
+                    let absolute_path = self.path.join(new_path.as_path());
+                    TrackedFile::new(&absolute_path, &self.path, self.attributes.conversion_for(&new_path))?
+                }
+            };
+            // A file that was already renamed keeps its first path
+            let origin = match staged_files.get(&file) {
+                Some(ChangeType::Renamed { from }) => Some(from.clone()),
+                _ => head_tree.contains_key(&file).then(|| file.clone())
+            };
+            self.staging_area.remove(file)?;
+            let change = match origin {
+                // Moved back to where it's committed
+                Some(from) if from == new_path => match &head_tree[&from] {
+                    committed if committed.get_blob_id() != snapshot.get_blob_id() => ChangeType::Modified,
+                    committed if committed.mode != snapshot.mode => ChangeType::ModeChanged,
+                    _ => continue
+                },
+                Some(from) => ChangeType::Renamed { from },
+                None => ChangeType::Added
+            };
+            self.staging_area.insert_snapshot(new_path, change, snapshot)?;
+        }
+        Ok(())
     }
 
-    pub fn status(&self) -> Result<()> {
-        println!("Changes to be committed:");
-        if self.staging_area.is_empty() {
-            println!("  No changes");
-        } else {
-            for (file, change_type) in self.staging_area.into_iter() {
-                println!("  {} {}", change_type.display(), file.display());
-            }
+    // Files in HEAD or staged, without the ones a staged rename moved away
+    fn tracked_files(&self, head_tree: &HashMap<RootRelativePath, TrackedFile>) -> BTreeSet<RootRelativePath> {
+        let rename_sources = self.staging_area.rename_sources();
+        head_tree.keys()
+            .filter(|file| !rename_sources.contains(file))
+            .chain(self.staging_area.into_iter().filter(|(_, staged)| !matches!(staged.change, ChangeType::Removed)).map(|(file, _)| file))
+            .cloned()
+            .collect()
+    }
+
+    fn relative_cli_path(&self, path: &Path) -> Result<RootRelativePath> {
+        let absolute_path = CliPath::from(path).to_unchecked_absolute()?;
+        RootRelativePath::new(&absolute_path, &self.path)
+    }
+
+    // Takes files out of the staging area, their changes stay in the working tree. Renames are also unstaged by their
+    // old path
+    pub fn unstage(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let pathspec = Pathspec::parse(paths, &self.path)?;
+        // I don't care if the user is trying to unstage a file that isn't added to the staging area
+        let staged: Vec<RootRelativePath> = self.staging_area.get_files().into_iter()
+            .filter(|(file, change)| pathspec.matches(file) || matches!(change, ChangeType::Renamed { from } if pathspec.matches(from)))
+            .map(|(file, _)| file)
+            .collect();
+        for file in staged {
+            self.staging_area.remove(file)?;
+        }
+        Ok(())
+    }
+
+    // Paths limit both lists to the files they select
+    pub fn status(&self, paths: &[PathBuf], options: &RenameOptions) -> Result<()> {
+        let pathspec = Pathspec::parse(paths, &self.path)?;
+        if let Head::Attached(branch) = &self.head && let Some(upstream) = self.upstream_status(branch)? {
+            println!("Tracking {}\n", upstream);
         }
+        let head_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        let index_tree = self.index_tree(&head_tree);
+        let staged_changes = self.staging_area.get_files().into_iter().collect();
+        let staged = self.compare(head_tree, NewSide::Tree(index_tree.clone()), staged_changes, options)?;
+        let unstaged_changes = self.unstaged_changes()?;
+        let unstaged = self.compare(index_tree, NewSide::WorkingTree, unstaged_changes, options)?;
+        self.stat_cache.save_if_changed()?;
 
+        println!("Changes to be committed:");
+        print_entries(&staged, &pathspec);
         println!("\nUnstaged changes:");
-        let changed_files = self.get_changed_files()?;
-        let mut unstaged_file_exists = false;
-        for (file, change_type) in changed_files {
-            if !self.staging_area.contains(&file) {
-                println!("  {} {}", change_type.display(), file.display());
-                unstaged_file_exists = true;
+        print_entries(&unstaged, &pathspec);
+        Ok(())
+    }
+
+    // Staged files are compared with the version that was staged instead of HEAD
+    pub(super) fn unstaged_changes(&self) -> Result<BTreeMap<RootRelativePath, ChangeType>> {
+        let mut unstaged = self.get_changed_files()?;
+        for (file, staged) in &self.staging_area {
+            unstaged.remove(file);
+            if let Some(snapshot) = &staged.snapshot && let Some(change) = self.change_since_staged(file, snapshot)? {
+                unstaged.insert(file.clone(), change);
+            } else if matches!(staged.change, ChangeType::Removed) && self.path.join(file.as_path()).is_file() {
+                // Removed with rm --cached, so the file on disk isn't tracked anymore
+                unstaged.insert(file.clone(), ChangeType::Added);
             }
         }
-        if !unstaged_file_exists {
-            println!("  No changes");
+        // The old paths of renamed files aren't tracked anymore either
+        for from in self.staging_area.rename_sources() {
+            let is_on_disk = self.path.join(from.as_path()).is_file();
+            unstaged.remove(&from);
+            if is_on_disk {
+                unstaged.insert(from, ChangeType::Added);
+            }
         }
-        Ok(())
+        Ok(unstaged)
+    }
+
+    fn change_since_staged(&self, file: &RootRelativePath, snapshot: &TrackedFile) -> Result<Option<ChangeType>> {
+        let absolute_path = self.path.join(file.as_path());
+        if !absolute_path.is_file() {
+            return Ok(Some(ChangeType::Removed));
+        }
+        let blob_id = self.stat_cache.blob_id(file, &absolute_path, self.attributes.conversion_for(file))?;
+        Ok(if blob_id != snapshot.get_blob_id() {
+            Some(ChangeType::Modified)
+        } else if FileMode::from_file(&absolute_path)? != snapshot.mode {
+            Some(ChangeType::ModeChanged)
+        } else {
+            None
+        })
     }
 
     pub fn commit(&mut self, message: String) -> Result<()> {
@@ -61,15 +267,57 @@ impl Root {
             return Err(GustError::User("Staged changes not found. Use 'gust add' to stage changes before committing".into()));
         }
 
-        let metadata = CommitMetadata::new(message);
-        let commit = CommitRef::new_commit(&self, metadata)?;
+        let old_commit = self.head_commit_id()?;
+        let metadata = CommitMetadata::new(message.clone());
+        let commit = CommitRef::new_commit(self, metadata)?;
+        let new_commit = Some(commit.get_commit_id().to_string());
+        println!("{}", commit.display_short());
+        let mut changes: Vec<_> = self.staging_area.into_iter().collect();
+        changes.sort_by_key(|(file, _)| *file);
+        for (file, staged) in changes {
+            println!("  {} {}", staged.change.display(), staged.change.display_path(file));
+        }
         self.head.insert_commit(commit)?;
+        self.log_head_update(old_commit, new_commit, &format!("commit: {}", message))?;
         self.staging_area.clear()?;
         Ok(())
     }
 
-    pub fn log(&self) -> Result<()> {
-        println!("{}", self.head.display());
+    // With stat, every commit is followed by the files it changed compared with the commit before it
+    pub fn log(&self, revision: &Option<String>, stat: bool, options: &RenameOptions) -> Result<()> {
+        if stat {
+            match revision {
+                Some(spec) => println!("Commit history of {}:", spec),
+                None => println!("{}", self.head.display_title())
+            }
+            for (parent, commit) in self.resolve_range_with_parents(revision.as_deref().unwrap_or("HEAD"))?.iter().rev() {
+                println!("{}", commit.display());
+                self.print_stat(parent.as_ref(), commit, options)?;
+            }
+            return Ok(());
+        }
+        match revision {
+            Some(spec) => {
+                println!("Commit history of {}:", spec);
+                for commit in self.resolve_range(spec)?.iter().rev() {
+                    println!("{}", commit.display());
+                }
+            },
+            None => println!("{}", self.head.display())
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Entries where either path matches, so a rename shows up when only one side is selected
+fn print_entries(comparison: &Comparison, pathspec: &Pathspec) {
+    let entries: Vec<_> = comparison.entries().into_iter()
+        .filter(|entry| pathspec.matches(entry.old_path) || pathspec.matches(entry.new_path))
+        .collect();
+    if entries.is_empty() {
+        println!("  No changes");
+    }
+    for entry in entries {
+        println!("  {}", entry.label());
+    }
+}
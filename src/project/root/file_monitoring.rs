@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use clap::Subcommand;
+use super::Root;
+use crate::project::error::{GustError, Result};
+use crate::project::fsmonitor::{self, MonitorState};
+use crate::project::paths::{AbsolutePath, RootRelativePath};
+use crate::project::storable::{ContainsStorePath, ProjectStorable};
+
+#[derive(Subcommand)]
+pub(crate) enum FsmonitorAction {
+    // Starts a background process that watches the working tree, only supported on Linux
+    Start,
+    Stop,
+    Status,
+    // Runs the daemon in the foreground, this is what start launches
+    #[command(hide = true)]
+    Run
+}
+
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The files in the working tree, and which of them can differ from HEAD
+pub(super) struct FileListing {
+    pub files: Vec<AbsolutePath>,
+    // The paths the daemon saw change since its last query, and the paths that differed from HEAD then. None when
+    // every file has to be examined
+    candidates: Option<BTreeSet<PathBuf>>,
+    monitor_state: Option<MonitorState>
+}
+
+impl FileListing {
+    // True if the path or one of its folders changed, or if it already differed from HEAD
+    pub fn may_have_changed(&self, path: &RootRelativePath) -> bool {
+        match &self.candidates {
+            Some(candidates) => path.as_path().ancestors().any(|a| candidates.contains(a)),
+            None => true
+        }
+    }
+
+    // Remembers which paths differ from HEAD for the next query
+    pub fn save(self, differing: BTreeSet<RootRelativePath>, head: Option<String>) -> Result<()> {
+        if let Some(mut monitor_state) = self.monitor_state {
+            monitor_state.state.differing = differing;
+            monitor_state.state.head = head;
+            monitor_state.save()?;
+        }
+        Ok(())
+    }
+}
+
+impl Root {
+    pub fn fsmonitor(&self, action: &FsmonitorAction) -> Result<()> {
+        match action {
+            FsmonitorAction::Start => self.start_fsmonitor(),
+            FsmonitorAction::Stop => {
+                fsmonitor::stop(&self.path)?;
+                println!("Stopped the fsmonitor daemon");
+                Ok(())
+            },
+            FsmonitorAction::Status => {
+                if fsmonitor::is_running(&self.path) {
+                    println!("The fsmonitor daemon is running");
+                } else {
+                    println!("The fsmonitor daemon isn't running");
+                }
+                Ok(())
+            },
+            FsmonitorAction::Run => fsmonitor::run_daemon(&self.path)
+        }
+    }
+
+    fn start_fsmonitor(&self) -> Result<()> {
+        if !cfg!(target_os = "linux") {
+            return Err(GustError::User("The fsmonitor daemon is only supported on Linux".into()));
+        }
+        if fsmonitor::is_running(&self.path) {
+            return Err(GustError::User("The fsmonitor daemon is already running".into()));
+        }
+        let mut command = Command::new(env::current_exe()?);
+        command.args(["fsmonitor", "run"])
+            .current_dir(self.path.as_path())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // Its own process group, so Ctrl-C in the terminal that started it doesn't stop it
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        command.spawn()?;
+
+        let mut waited = Duration::ZERO;
+        while !fsmonitor::is_running(&self.path) {
+            if waited >= START_TIMEOUT {
+                return Err(GustError::User("The fsmonitor daemon didn't start".into()));
+            }
+            thread::sleep(Duration::from_millis(50));
+            waited += Duration::from_millis(50);
+        }
+        println!("Started the fsmonitor daemon");
+        Ok(())
+    }
+
+    // The files scan_folder would find in the whole working tree. When the fsmonitor daemon is running, only the paths
+    // it saw change since the last query are read again, the rest comes from the files found by that query
+    pub(super) fn list_files(&self) -> Result<FileListing> {
+        let root_folder = AbsolutePath::from_absolute_path(self.path.as_path());
+        let mut monitor_state = MonitorState::new(self.path.clone(), true)?;
+        let Some(update) = fsmonitor::query(&self.path, monitor_state.state.token.as_deref()) else {
+            return Ok(FileListing { files: self.scan_folder(&root_folder)?, candidates: None, monitor_state: None });
+        };
+
+        let mut candidates = None;
+        let files = match update.changed.filter(|_| monitor_state.state.token.is_some()) {
+            // The ignore rules apply to every file, so they can't be updated path by path
+            Some(changed) if !changed.iter().any(|p| p == Path::new(".gustignore")) => {
+                // The attributes change the blob of every file, and another HEAD changes what every file is compared with
+                if monitor_state.state.head == self.head_commit_id()? && !changed.iter().any(|p| p == Path::new(".gustattributes")) {
+                    let differing = monitor_state.state.differing.iter().map(|p| p.as_path().to_path_buf());
+                    candidates = Some(changed.iter().cloned().chain(differing).collect());
+                }
+                self.update_files(&monitor_state.state.files, &changed)?
+            },
+            _ => self.scan_folder(&root_folder)?.iter().map(|f| RootRelativePath::new(f, &self.path)).collect::<Result<_>>()?
+        };
+        let absolute_files = files.iter().map(|f| self.path.join(f.as_path())).collect();
+        // Saved along with the differing paths, so a command that fails doesn't lose the changes of this query
+        monitor_state.state.token = Some(update.token);
+        monitor_state.state.files = files;
+        Ok(FileListing { files: absolute_files, candidates, monitor_state: Some(monitor_state) })
+    }
+
+    // Drops every known file that is or is inside a changed path, then reads the changed paths that still exist
+    fn update_files(&self, known: &BTreeSet<RootRelativePath>, changed: &[PathBuf]) -> Result<BTreeSet<RootRelativePath>> {
+        let changed_set: BTreeSet<&Path> = changed.iter().map(PathBuf::as_path).collect();
+        let mut files: BTreeSet<RootRelativePath> = known.iter()
+            .filter(|f| !f.as_path().ancestors().any(|a| changed_set.contains(a)))
+            .cloned()
+            .collect();
+        for path in changed {
+            let absolute_path = self.path.join(path);
+            if !absolute_path.exists() || !self.would_be_scanned(path) {
+                continue;
+            }
+            if absolute_path.is_dir() {
+                for file in self.scan_folder(&absolute_path)? {
+                    files.insert(RootRelativePath::new(&file, &self.path)?);
+                }
+            } else {
+                files.insert(RootRelativePath::from_relative_path(path));
+            }
+        }
+        Ok(files)
+    }
+
+    // Mirrors what read_folder skips: .gust, ignored paths and other projects inside this one
+    fn would_be_scanned(&self, path: &Path) -> bool {
+        if path.starts_with(".gust") {
+            return false;
+        }
+        path.ancestors().filter(|a| !a.as_os_str().is_empty()).all(|a| {
+            let absolute_path = self.path.join(a);
+            let is_nested_project = absolute_path.as_path().join(".gust").exists();
+            // Paths inside an ignored folder are an error for is_path_ignored
+            !is_nested_project && !self.is_path_ignored(&absolute_path).unwrap_or(true)
+        })
+    }
+}
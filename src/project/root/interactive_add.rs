@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::process::Command;
+use crate::project::diff::{self, Edit, HunkChoice};
+use crate::project::error::{GustError, Result};
+use crate::project::paths::RootRelativePath;
+use crate::project::pathspec::Pathspec;
+use crate::project::staging_area::ChangeType;
+use crate::project::tracked_file::{blob_content, FileMode, TrackedFile};
+use super::Root;
+
+const HELP: &str = "y - stage this hunk
+n - do not stage this hunk
+q - quit; do not stage this hunk or any of the remaining ones
+a - stage this hunk and all later hunks in the file
+d - do not stage this hunk or any of the later hunks in the file
+s - split the current hunk into smaller hunks
+e - manually edit the current hunk
+? - print help";
+
+impl Root {
+    // Walks the hunks of every modified file and asks which ones to stage. Answers are read line by line from stdin,
+    // and running out of them is the same as quitting
+    pub fn add_patch(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let pathspec = Pathspec::parse(paths, &self.path)?;
+        let Some(head) = self.get_last_commit()? else {
+            println!("No changes");
+            return Ok(());
+        };
+        let mut answers = io::stdin().lock().lines();
+        let mut any_change = false;
+
+        for (file, change) in self.get_changed_files()? {
+            let absolute_path = self.path.join(file.as_path());
+            if !matches!(change, ChangeType::Modified) || !pathspec.matches(&file) || absolute_path.is_symlink() {
+                continue;
+            }
+            any_change = true;
+            let Some(committed) = head.get_file(&file) else { continue; };
+            // Hunks that are already staged aren't asked again
+            let old = match self.staging_area.get_snapshot(&file) {
+                Some(snapshot) => snapshot.read_blob(&self.path)?,
+                None => committed.read_blob(&self.path)?
+            };
+            let conversion = self.attributes.conversion_for(&file);
+            let new = blob_content(absolute_path.as_path(), conversion)?;
+            if old.contains(&0) || new.contains(&0) {
+                println!("Skipping binary file {}", file.display());
+                continue;
+            }
+
+            let Some((content, quit)) = self.choose_hunks(&file, &old, &new, &mut answers)? else { continue; };
+            if content == new {
+                self.stage_file(file, ChangeType::Modified)?;
+            } else if content != old {
+                let snapshot = TrackedFile::from_content(&content, FileMode::from_file(&absolute_path)?, &self.path)?;
+                self.staging_area.insert_snapshot(file, ChangeType::Modified, snapshot)?;
+            }
+            if quit {
+                break;
+            }
+        }
+        if !any_change {
+            println!("No changes");
+        }
+        Ok(())
+    }
+
+    // Returns the old content with the chosen hunks applied, and whether the user quit. None if there's nothing to ask
+    fn choose_hunks(&self, file: &RootRelativePath, old: &[u8], new: &[u8], answers: &mut impl Iterator<Item = io::Result<String>>) -> Result<Option<(Vec<u8>, bool)>> {
+        let (old_lines, new_lines) = (diff::split_lines(old), diff::split_lines(new));
+        let edits = diff::diff_lines(&old_lines, &new_lines);
+        let mut pending: VecDeque<Range<usize>> = diff::hunks(&edits, diff::CONTEXT_LINES).into();
+        if pending.is_empty() {
+            return Ok(None);
+        }
+        println!("diff --gust a/{0} b/{0}", file.display());
+
+        let mut choices: Vec<(Range<usize>, HunkChoice)> = Vec::new();
+        // Set by a, d and q, for the rest of the file's hunks
+        let mut remaining_choice: Option<HunkChoice> = None;
+        let mut quit = false;
+        while let Some(hunk) = pending.pop_front() {
+            if let Some(choice) = &remaining_choice {
+                choices.push((hunk, choice.clone()));
+                continue;
+            }
+            diff::print_hunk(&edits, &hunk, &old_lines, &new_lines);
+            print!("Stage this hunk [y,n,q,a,d,s,e,?]? ");
+            io::stdout().flush()?;
+            let answer = answers.next().transpose()?.unwrap_or_else(|| "q".into());
+            println!();
+            match answer.trim() {
+                "y" => choices.push((hunk, HunkChoice::Take)),
+                "n" => choices.push((hunk, HunkChoice::Skip)),
+                "a" | "d" | "q" => {
+                    let choice = if answer.trim() == "a" { HunkChoice::Take } else { HunkChoice::Skip };
+                    quit = answer.trim() == "q";
+                    choices.push((hunk, choice.clone()));
+                    remaining_choice = Some(choice);
+                },
+                "s" => match diff::split_hunk(&edits, &hunk) {
+                    Some(pieces) => {
+                        println!("Split into {} hunks.", pieces.len());
+                        pieces.into_iter().rev().for_each(|piece| pending.push_front(piece));
+                    },
+                    None => {
+                        println!("Sorry, cannot split this hunk");
+                        pending.push_front(hunk);
+                    }
+                },
+                "e" => match self.edit_hunk(&edits, &hunk, &old_lines, &new_lines)? {
+                    Some(lines) => choices.push((hunk, HunkChoice::Replace(lines))),
+                    None => pending.push_front(hunk)
+                },
+                _ => {
+                    println!("{}", HELP);
+                    pending.push_front(hunk);
+                }
+            }
+        }
+        Ok(Some((diff::apply_hunks(&edits, &choices, &old_lines, &new_lines), quit)))
+    }
+
+    // Opens the hunk in $GUST_EDITOR or $EDITOR. The lines kept as ' ' or '+' replace the hunk. None if the edited hunk
+    // doesn't start from the same old lines, so it can't be applied
+    fn edit_hunk(&self, edits: &[Edit], hunk: &Range<usize>, old: &[&[u8]], new: &[&[u8]]) -> Result<Option<Vec<Vec<u8>>>> {
+        let edit_path = self.path.gust_join("ADD_EDIT.hunk");
+        let mut content = b"# Manual hunk edit mode\n# To remove '-' lines, make them ' ' lines (context).\n# To remove '+' lines, delete them.\n# Lines starting with # will be removed.\n".to_vec();
+        for (prefix, line) in diff::hunk_lines(edits, hunk, old, new) {
+            content.push(prefix as u8);
+            content.extend_from_slice(line);
+            if !line.ends_with(b"\n") {
+                content.push(b'\n');
+            }
+        }
+        fs::write(edit_path.as_path(), &content)?;
+
+        let editor = env::var("GUST_EDITOR").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| "vi".into());
+        let status = Command::new("sh").arg("-c").arg(format!("{} \"$0\"", editor)).arg(edit_path.as_path()).status()?;
+        let edited = fs::read(edit_path.as_path());
+        fs::remove_file(edit_path.as_path())?;
+        if !status.success() {
+            return Err(GustError::User(format!("The editor {} failed", editor)));
+        }
+
+        let edited = edited?;
+        let (mut before, mut after) = (Vec::new(), Vec::new());
+        for line in diff::split_lines(&edited).into_iter().filter(|l| !l.starts_with(b"#")) {
+            let (prefix, rest) = line.split_first().unwrap_or((&b' ', b""));
+            match prefix {
+                b' ' => {
+                    before.push(rest);
+                    after.push(rest.to_vec());
+                },
+                b'-' => before.push(rest),
+                b'+' => after.push(rest.to_vec()),
+                // An empty line is an empty context line
+                b'\n' => {
+                    before.push(b"\n");
+                    after.push(b"\n".to_vec());
+                },
+                _ => {
+                    println!("Your edited hunk does not apply");
+                    return Ok(None);
+                }
+            }
+        }
+        let original: Vec<&[u8]> = edits[hunk.clone()].iter().filter_map(|e| match *e {
+            Edit::Equal(o, _) | Edit::Delete(o) => Some(old[o]),
+            Edit::Insert(_) => None
+        }).collect();
+        let trim = |line: &[u8]| line.strip_suffix(b"\n").unwrap_or(line).to_vec();
+        if before.len() != original.len() || before.iter().zip(&original).any(|(a, b)| trim(a) != trim(b)) {
+            println!("Your edited hunk does not apply");
+            return Ok(None);
+        }
+        // The old version's last line may not have a line ending, and the edit shouldn't add one
+        if let (Some(last_original), Some(last)) = (original.last(), after.last_mut())
+            && !last_original.ends_with(b"\n") && hunk.end == edits.len() {
+            last.pop_if(|b| *b == b'\n');
+        }
+        Ok(Some(after))
+    }
+}
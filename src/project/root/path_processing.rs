@@ -1,94 +1,108 @@
-use std::collections::{HashMap};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
 use crate::project::commit::{Commit, FileStatus};
 use crate::project::error::{GustError, Result};
-use crate::project::paths::{AbsolutePath, CliPath, RootRelativePath};
+use crate::project::paths::{AbsolutePath, RootRelativePath};
+use crate::project::parallel::parallel_map;
 use crate::project::staging_area::ChangeType;
 use super::{Root};
 
 impl Root {
-    // Converts PathBuf to RootRelativePath and checks if the path exists and is inside the project
-    pub(super) fn process_path_list<F>(&mut self, paths: &Vec<PathBuf>, mut apply: F) -> Result<()>
-    where F: FnMut(&mut Self, &AbsolutePath) -> Result<()>
-    {
-        for cli_path in paths.iter().map(|p| CliPath::from(p.as_path())) {
-            let absolute_path = AbsolutePath::try_from(cli_path)?;
-            if !self.path.is_inside_root(&absolute_path) {
-                return Err(GustError::User(format!("Path {:?} is not a root path", absolute_path)));
-            }
-            if absolute_path.is_dir() {
-                for file in self.scan_folder(&absolute_path)? {
-                    apply(self, &file)?;
-                }
-            } else {
-                apply(self, &absolute_path)?;
-            }
-        }
-        Ok(())
-    }
-
-    pub(super) fn get_changed_files(&self) -> Result<HashMap<RootRelativePath, ChangeType>> {
-        let files = self.scan_folder(&AbsolutePath::from_absolute_path(self.path.as_path()))?;
+    // Sorted by path. Files are hashed in parallel, and only if the stat cache doesn't know their blob. With the
+    // fsmonitor daemon running, files it didn't see change are skipped unless they differed from HEAD or are staged
+    pub(super) fn get_changed_files(&self) -> Result<BTreeMap<RootRelativePath, ChangeType>> {
+        let listing = self.list_files()?;
         // Change the get_last_commit_ref name to get_head_tree or something like that
         let commit = Commit::from_commit_ref_option(self.head.get_tree()?, &self.path)?;
-        let mut changed_files: HashMap<RootRelativePath, ChangeType> = HashMap::new();
+        let mut changed_files: BTreeMap<RootRelativePath, ChangeType> = BTreeMap::new();
+        let needs_examining = |path: &RootRelativePath| listing.may_have_changed(path) || self.staging_area.contains(path);
 
         // Check in the project root directory for changed files
-        for file in files {
-            let relative_file_path = RootRelativePath::new(&file, &self.path)?;
-            if let Some(c) = commit.as_ref() {
-                match c.has_file_changed(&relative_file_path, &file)? {
-                    FileStatus::Unchanged => continue,
-                    status => {
-                        let change_type = match status { 
-                            FileStatus::Added => ChangeType::Added, 
-                            FileStatus::Modified => ChangeType::Modified,
-                            _ => unreachable!()
-                        };
-                        changed_files.insert(relative_file_path, change_type);
-                    }
-                }
-            } else {
-                changed_files.insert(relative_file_path, ChangeType::Added);
+        let relative_files = listing.files.iter().map(|file| RootRelativePath::new(file, &self.path)).collect::<Result<Vec<_>>>()?;
+        let examined: Vec<(&AbsolutePath, &RootRelativePath)> = listing.files.iter().zip(&relative_files)
+            .filter(|(_, relative_file_path)| needs_examining(relative_file_path))
+            .collect();
+        let changes = parallel_map(&examined, self.jobs, |(file, relative_file_path)| -> Result<Option<ChangeType>> {
+            let Some(c) = commit.as_ref() else { return Ok(Some(ChangeType::Added)); };
+            let blob_id = self.stat_cache.blob_id(relative_file_path, file, self.attributes.conversion_for(relative_file_path))?;
+            Ok(match c.has_file_changed(relative_file_path, file, &blob_id)? {
+                FileStatus::Unchanged => None,
+                FileStatus::Added => Some(ChangeType::Added),
+                FileStatus::Modified => Some(ChangeType::Modified),
+                FileStatus::ModeChanged => Some(ChangeType::ModeChanged)
+            })
+        });
+        for ((_, relative_file_path), change) in examined.iter().zip(changes) {
+            if let Some(change_type) = change? {
+                changed_files.insert((*relative_file_path).clone(), change_type);
             }
         }
 
         if let Some(c) = commit.as_ref() {
-            for tracked_file in c.tree_iterator() {
+            for tracked_file in c.tree_iterator().filter(|(path, _)| needs_examining(path)) {
                 let absolute_path = &self.path.join(tracked_file.0.as_path());
-                if !absolute_path.as_path().exists() {
+                // A folder where a tracked file used to be also means the file was removed. Paths outside the sparse
+                // checkout aren't on disk on purpose
+                if !absolute_path.is_file() && self.is_materialized(tracked_file.0) {
                     changed_files.insert(tracked_file.0.clone(), ChangeType::Removed);
                 }
             }
         }
 
+        let scanned: HashSet<&RootRelativePath> = relative_files.iter().collect();
+        self.stat_cache.retain(|path| scanned.contains(path));
+        self.stat_cache.save_if_changed()?;
+        listing.save(changed_files.keys().cloned().collect(), self.head_commit_id()?)?;
         Ok(changed_files)
     }
 
+    // Folders are read in parallel, one level of the tree at a time. The result is sorted, so it doesn't depend on
+    // which thread finishes first
     pub(super) fn scan_folder(&self, path: &AbsolutePath) -> Result<Vec<AbsolutePath>> {
-        if path.as_path() == &self.path.as_path().join(".gust") {
-            return Ok(Vec::new()); // Dont process the root .gust folder
-        } else if path.as_path().starts_with(&self.path.as_path().join(".gust/")) {
+        let mut files = Vec::new();
+        let mut level = vec![path.clone()];
+        while !level.is_empty() {
+            let mut next_level = Vec::new();
+            for result in parallel_map(&level, self.jobs, |folder| self.read_folder(folder)) {
+                let (folder_files, sub_folders) = result?;
+                files.extend(folder_files);
+                next_level.extend(sub_folders);
+            }
+            level = next_level;
+        }
+        files.sort_by(|a, b| a.as_path().cmp(b.as_path()));
+        Ok(files)
+    }
+
+    // Returns the files in the folder, and the sub folders that have to be scanned too
+    fn read_folder(&self, path: &AbsolutePath) -> Result<(Vec<AbsolutePath>, Vec<AbsolutePath>)> {
+        if path.as_path() == self.path.as_path().join(".gust") {
+            return Ok((Vec::new(), Vec::new())); // Dont process the root .gust folder
+        } else if path.as_path().starts_with(self.path.as_path().join(".gust/")) {
             return Err(GustError::User(format!("Path {} is inside .gust", path.as_path().display())));
         } else if self.is_path_ignored(path)? {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), Vec::new()));
         }
 
-        let entries = fs::read_dir(path.as_path()).unwrap(); // I know the path exists and is a dir
+        let entries = fs::read_dir(path.as_path())?;
         let mut files = Vec::new();
+        let mut folders = Vec::new();
 
         for entry in entries {
-            let entry_path = AbsolutePath::from_absolute_path(&entry.unwrap().path());
+            let entry_path = AbsolutePath::from_absolute_path(&entry?.path());
+            // Linked worktrees have a .gust file instead of a folder
+            if entry_path.as_path() == self.path.join(".gust").as_path() {
+                continue;
+            }
             if entry_path.is_dir() {
-                let entry_result = self.scan_folder(&entry_path)?;
-                files.extend(entry_result);
-            } else {
-                if !self.is_path_ignored(&entry_path)? {
-                    files.push(entry_path);
+                // Worktrees and other projects inside this one have their own status
+                if !entry_path.as_path().join(".gust").exists() {
+                    folders.push(entry_path);
                 }
+            } else if !self.is_path_ignored(&entry_path)? {
+                files.push(entry_path);
             }
         }
-        Ok(files)
+        Ok((files, folders))
     }
-}
\ No newline at end of file
+}
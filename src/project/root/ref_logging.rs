@@ -0,0 +1,65 @@
+use super::{Root, Head};
+use crate::project::error::{GustError, Result};
+use crate::project::ref_name::check_ref_lookup;
+use crate::project::reflog::Reflog;
+
+impl Root {
+    pub fn reflog(&self, name: &Option<String>) -> Result<()> {
+        let name = name.as_deref().unwrap_or("HEAD");
+        check_ref_lookup(name, "ref")?;
+        let reflog = Reflog::for_ref(&self.path, name);
+        if !reflog.exists() {
+            return Err(GustError::User(format!("No reflog found for {}", name)));
+        }
+        for (index, entry) in reflog.entries()?.iter().rev().enumerate() {
+            println!("{}", entry.display(name, index));
+        }
+        Ok(())
+    }
+
+    pub(super) fn head_commit_id(&self) -> Result<Option<String>> {
+        Ok(self.head.get_tree()?.map(|c| c.get_commit_id().to_string()))
+    }
+
+    pub(super) fn head_name(&self) -> &str {
+        match &self.head {
+            Head::Attached(branch) => &branch.name,
+            Head::Detached(branch) => &branch.passed_hash
+        }
+    }
+
+    // Records a movement of HEAD. If HEAD is attached, the branch moved with it
+    pub(super) fn log_head_update(&self, old: Option<String>, new: Option<String>, operation: &str) -> Result<()> {
+        if let Head::Attached(branch) = &self.head {
+            self.log_branch_update(&branch.name, old.clone(), new.clone(), operation)?;
+        }
+        Reflog::for_ref(&self.path, "HEAD").append(old, new, operation)
+    }
+
+    // Records a checkout, which moves HEAD without moving any branch
+    pub(super) fn log_checkout(&self, old: Option<String>, from: &str) -> Result<()> {
+        let operation = format!("checkout: moving from {} to {}", from, self.head_name());
+        Reflog::for_ref(&self.path, "HEAD").append(old, self.head_commit_id()?, &operation)
+    }
+
+    pub(super) fn log_branch_update(&self, name: &str, old: Option<String>, new: Option<String>, operation: &str) -> Result<()> {
+        Reflog::for_ref(&self.path, name).append(old, new, operation)
+    }
+
+    // Resolves <ref>@{n}, where an empty ref means HEAD. Returns None if the name isn't a reflog reference
+    pub(super) fn resolve_reflog_ref(&self, name: &str) -> Result<Option<String>> {
+        let Some((ref_name, index)) = name.strip_suffix('}').and_then(|n| n.rsplit_once("@{")) else {
+            return Ok(None);
+        };
+        let ref_name = if ref_name.is_empty() { "HEAD" } else { ref_name };
+        check_ref_lookup(ref_name, "ref")?;
+        let index: usize = index.parse()
+            .map_err(|_| GustError::User(format!("Invalid reflog reference {}", name)))?;
+
+        let reflog = Reflog::for_ref(&self.path, ref_name);
+        if !reflog.exists() {
+            return Err(GustError::User(format!("No reflog found for {}", ref_name)));
+        }
+        Ok(Some(reflog.get(index)?))
+    }
+}
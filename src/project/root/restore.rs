@@ -0,0 +1,93 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use crate::project::commit::Commit;
+use crate::project::error::{GustError, Result};
+use crate::project::paths::{remove_empty_parent_dirs, CliPath, RootRelativePath};
+use crate::project::staging_area::ChangeType;
+use crate::project::storable::ProjectStorable;
+use crate::project::tracked_file::TrackedFile;
+use super::Root;
+
+impl Root {
+    pub fn restore(&mut self, source: &Option<String>, staged: bool, worktree: bool, paths: &[PathBuf]) -> Result<()> {
+        // Without any target, only the working tree is restored
+        let worktree = worktree || !staged;
+
+        let head_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        let source_tree = match source {
+            Some(name) => {
+                let hash = self.resolve_commit_id(name)?;
+                Commit::load((self.path.clone(), hash))?.copy_tree()
+            },
+            None => head_tree.clone()
+        };
+
+        let mut relative_paths = Vec::new();
+        for cli_path in paths {
+            let absolute_path = CliPath::from(cli_path.as_path()).to_unchecked_absolute()?;
+            let relative_path = RootRelativePath::new(&absolute_path, &self.path)?;
+            let is_known = source_tree.keys()
+                .chain(head_tree.keys())
+                .chain(self.staging_area.into_iter().map(|(file, _)| file))
+                .any(|file| file.as_path().starts_with(relative_path.as_path()));
+            if !is_known {
+                return Err(GustError::User(format!("Path {} did not match any tracked file", relative_path.display())));
+            }
+            relative_paths.push(relative_path);
+        }
+        let matches = |file: &RootRelativePath| relative_paths.iter().any(|p| file.as_path().starts_with(p.as_path()));
+
+        if worktree {
+            // Paths outside the sparse checkout stay off disk
+            for (file, tracked_file) in source_tree.iter().filter(|(file, _)| matches(file) && self.is_materialized(file)) {
+                self.restore_file(file, tracked_file)?;
+            }
+            // Files tracked by HEAD that don't exist in the source get deleted. Untracked files are left alone
+            for file in head_tree.keys().filter(|file| matches(file) && !source_tree.contains_key(file)) {
+                let absolute_path = self.path.join(file.as_path());
+                if absolute_path.exists() {
+                    fs::remove_file(absolute_path.as_path())?;
+                    remove_empty_parent_dirs(absolute_path.as_path(), self.path.as_path())?;
+                }
+            }
+        }
+
+        if staged {
+            let staged_files: Vec<RootRelativePath> = self.staging_area.get_files().into_keys().filter(|file| matches(file)).collect();
+            for file in staged_files {
+                self.staging_area.remove(file)?;
+            }
+            // The source's version is staged straight from its tree, so files that are in neither tree are never
+            // staged. Without a source, unstaging is enough
+            if source.is_some() {
+                let files: BTreeSet<&RootRelativePath> = source_tree.keys().chain(head_tree.keys()).filter(|file| matches(file)).collect();
+                for file in files {
+                    match (source_tree.get(file), head_tree.get(file)) {
+                        (Some(source_file), Some(head_file)) if source_file.get_blob_id() != head_file.get_blob_id() => {
+                            self.staging_area.insert_snapshot(file.clone(), ChangeType::Modified, source_file.clone())?;
+                        },
+                        (Some(source_file), Some(head_file)) if source_file.mode != head_file.mode => {
+                            self.staging_area.insert_snapshot(file.clone(), ChangeType::ModeChanged, source_file.clone())?;
+                        },
+                        (Some(source_file), None) => self.staging_area.insert_snapshot(file.clone(), ChangeType::Added, source_file.clone())?,
+                        (None, Some(_)) => self.staging_area.insert_removal(file.clone())?,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn restore_file(&self, file: &RootRelativePath, tracked_file: &TrackedFile) -> Result<()> {
+        let destination_path = self.path.join(file.as_path());
+        if let Some(parent) = destination_path.as_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conversion = self.attributes.conversion_for(file);
+        tracked_file.write_to(&destination_path, &self.path, conversion)?;
+        self.stat_cache.record(file, &destination_path, conversion, tracked_file.get_blob_id())?;
+        self.stat_cache.save_if_changed()
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+use std::fs;
+use super::{Root, Head};
+use crate::project::branch::{Branch, BranchTrait};
+use crate::project::commit::{Commit, CommitRef};
+use crate::project::error::GustError;
+use crate::project::storable::ProjectStorable;
+use crate::project::ref_name::is_lookup_safe;
+use crate::project::tag::Tag;
+
+pub(super) enum RevisionError {
+    NotFound(String),
+    Ambiguous(String, Vec<String>),
+    NormalError(GustError)
+}
+
+impl From<std::io::Error> for RevisionError {
+    fn from(value: std::io::Error) -> Self {
+        RevisionError::NormalError(GustError::Io(value))
+    }
+}
+impl From<GustError> for RevisionError {
+    fn from(value: GustError) -> Self {
+        RevisionError::NormalError(value)
+    }
+}
+impl From<RevisionError> for GustError {
+    fn from(value: RevisionError) -> Self {
+        match value {
+            RevisionError::NotFound(message) => GustError::User(message),
+            RevisionError::Ambiguous(name, candidates) => GustError::User(format!("{} is ambiguous, it matches:\n  {}", name, candidates.join("\n  "))),
+            RevisionError::NormalError(error) => error
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, RevisionError>;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum RevisionKind {
+    Branch,
+    Tag,
+    Commit
+}
+
+enum Candidate {
+    Branch(String),
+    Tag(String),
+    Commit(String)
+}
+
+pub(super) struct Revision {
+    // Commits that lead to the revision, oldest first. The last one is the commit the revision points to
+    pub history: Vec<CommitRef>,
+    // Set when the revision is exactly a branch, so checkout can attach HEAD to it
+    pub branch: Option<String>
+}
+
+impl Revision {
+    pub fn tip(&self) -> Option<&CommitRef> { self.history.last() }
+}
+
+impl Candidate {
+    fn kind(&self) -> RevisionKind {
+        match self {
+            Candidate::Branch(_) => RevisionKind::Branch,
+            Candidate::Tag(_) => RevisionKind::Tag,
+            Candidate::Commit(_) => RevisionKind::Commit
+        }
+    }
+    fn describe(&self) -> String {
+        match self {
+            Candidate::Branch(name) => format!("branch {}", name),
+            Candidate::Tag(name) => format!("tag {}", name),
+            Candidate::Commit(hash) => format!("commit {}", hash)
+        }
+    }
+}
+
+impl Root {
+    // Accepts branches, tags, full or abbreviated hashes, HEAD and reflog references, followed by any number of ~N and ^
+    pub(super) fn resolve_revision(&self, spec: &str) -> Result<Revision> {
+        self.resolve_revision_of_kind(spec, None)
+    }
+
+    // Same as resolve_revision, but names that aren't of the given kind are ignored
+    pub(super) fn resolve_revision_of_kind(&self, spec: &str, kind: Option<RevisionKind>) -> Result<Revision> {
+        let (base, modifiers) = spec.split_at(spec.find(['~', '^']).unwrap_or(spec.len()));
+        let mut revision = self.resolve_base_revision(base, kind)?;
+
+        let steps = parse_ancestor_steps(spec, modifiers)?;
+        if steps > 0 {
+            if steps >= revision.history.len() {
+                return Err(RevisionError::NotFound(format!("{} goes back further than the history of {}, which has {} commits", spec, base, revision.history.len())));
+            }
+            revision.history.truncate(revision.history.len() - steps);
+            revision.branch = None;
+        }
+        Ok(revision)
+    }
+
+    pub(super) fn resolve_commit_id(&self, spec: &str) -> Result<String> {
+        match self.resolve_revision(spec)?.tip() {
+            Some(commit) => Ok(commit.get_commit_id().to_string()),
+            None => Err(RevisionError::NotFound(format!("{} doesn't have any commits", spec)))
+        }
+    }
+
+    // A..B is every commit in B's history that isn't in A's. A missing side means HEAD, and a single revision is its whole history
+    pub(super) fn resolve_range(&self, spec: &str) -> Result<Vec<CommitRef>> {
+        Ok(self.resolve_range_with_parents(spec)?.into_iter().map(|(_, commit)| commit).collect())
+    }
+
+    // Same as resolve_range, with the commit before each one in its history. The first commit doesn't have one
+    pub(super) fn resolve_range_with_parents(&self, spec: &str) -> Result<Vec<(Option<CommitRef>, CommitRef)>> {
+        let (from, to) = match spec.split_once("..") {
+            Some((from, to)) => (Some(self.resolve_revision(if from.is_empty() { "HEAD" } else { from })?), if to.is_empty() { "HEAD" } else { to }),
+            None => (None, spec)
+        };
+        let to = self.resolve_revision(to)?;
+
+        let excluded: HashSet<&str> = from.iter().flat_map(|from| from.history.iter().map(|c| c.get_commit_id())).collect();
+        Ok(to.history.iter().enumerate()
+            .filter(|(_, c)| !excluded.contains(c.get_commit_id()))
+            .map(|(i, c)| (i.checked_sub(1).map(|parent| to.history[parent].clone()), c.clone()))
+            .collect())
+    }
+
+    fn resolve_base_revision(&self, name: &str, kind: Option<RevisionKind>) -> Result<Revision> {
+        if name == "HEAD" {
+            return Ok(match &self.head {
+                Head::Attached(branch) => Revision { history: branch.commits().clone(), branch: Some(branch.name.clone()) },
+                Head::Detached(branch) => Revision { history: branch.commits().clone(), branch: None }
+            });
+        }
+        if let Some(hash) = self.resolve_reflog_ref(name)? {
+            return Ok(Revision { history: self.history_of_commit(&hash)?, branch: None });
+        }
+
+        let mut candidates = self.find_candidates(name)?;
+        if let Some(kind) = kind {
+            candidates.retain(|c| c.kind() == kind);
+        }
+        match candidates.as_slice() {
+            [] => Err(RevisionError::NotFound(format!("Revision {} not found", name))),
+            [Candidate::Branch(branch_name)] => {
+                let branch = Branch::load((self.path.clone(), branch_name.clone()))?;
+                Ok(Revision { history: branch.commits().clone(), branch: Some(branch_name.clone()) })
+            },
+            [Candidate::Tag(tag_name)] => {
+                let tag = Tag::load((self.path.clone(), tag_name.clone()))?;
+                Ok(Revision { history: self.history_of_commit(tag.get_commit_id())?, branch: None })
+            },
+            [Candidate::Commit(hash)] => Ok(Revision { history: self.history_of_commit(hash)?, branch: None }),
+            _ => Err(RevisionError::Ambiguous(name.to_string(), candidates.iter().map(|c| c.describe()).collect()))
+        }
+    }
+
+    fn find_candidates(&self, name: &str) -> Result<Vec<Candidate>> {
+        if name.is_empty() {
+            return Err(RevisionError::NotFound("Empty revision".into()));
+        }
+        let mut candidates = Vec::new();
+        // A name that would leave the refs folder can still be a commit prefix, but never a branch or a tag
+        if is_lookup_safe(name) {
+            if Branch::build_absolute_path(&(self.path.clone(), name.to_string())).as_path().exists() {
+                candidates.push(Candidate::Branch(name.to_string()));
+            }
+            if Tag::build_absolute_path(&(self.path.clone(), name.to_string())).as_path().exists() {
+                candidates.push(Candidate::Tag(name.to_string()));
+            }
+        }
+        for commit in fs::read_dir(self.path.common_join("commits").as_path())? {
+            let commit_name = commit?.path().file_stem().unwrap().to_str().unwrap().to_string();
+            if commit_name.starts_with(name) {
+                candidates.push(Candidate::Commit(commit_name));
+            }
+        }
+        Ok(candidates)
+    }
+
+    // Commits don't store their parents, so the history is taken from the first branch that contains the commit
+    fn history_of_commit(&self, commit_id: &str) -> Result<Vec<CommitRef>> {
+        let position = |commits: &Vec<CommitRef>| commits.iter().position(|c| c.get_commit_id() == commit_id);
+
+        let head_commits = match &self.head {
+            Head::Attached(branch) => branch.commits(),
+            Head::Detached(branch) => branch.commits()
+        };
+        if let Some(index) = position(head_commits) {
+            return Ok(head_commits[..=index].to_vec());
+        }
+        for branch_name in self.branch_names()? {
+            let branch = Branch::load((self.path.clone(), branch_name))?;
+            if let Some(index) = position(branch.commits()) {
+                return Ok(branch.commits()[..=index].to_vec());
+            }
+        }
+
+        // Commits that aren't on any branch (e.g. found through the reflog) have no known history
+        let commit = Commit::load((self.path.clone(), commit_id.to_string()))?;
+        Ok(vec![CommitRef::new_from_existing(&commit, commit_id.to_string())])
+    }
+}
+
+fn parse_ancestor_steps(spec: &str, modifiers: &str) -> Result<usize> {
+    let mut steps = 0;
+    let mut chars = modifiers.chars().peekable();
+    while let Some(modifier) = chars.next() {
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+            digits.push(digit);
+        }
+        let count: usize = if digits.is_empty() { 1 } else {
+            digits.parse().map_err(|_| RevisionError::NotFound(format!("Invalid revision {}", spec)))?
+        };
+        match modifier {
+            '~' => steps += count,
+            // ^N picks the Nth parent, and commits only have one
+            '^' if count <= 1 => steps += count,
+            '^' => return Err(RevisionError::NotFound(format!("{} doesn't exist, commits only have one parent", spec))),
+            _ => return Err(RevisionError::NotFound(format!("Invalid revision {}", spec)))
+        }
+    }
+    Ok(steps)
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use clap::Subcommand;
+use crate::project::paths::RootRelativePath;
+use crate::project::pathspec::Pathspec;
+use super::{Root, RootPath};
+use crate::project::error::{GustError, Result};
+
+#[derive(Subcommand)]
+pub(crate) enum SparseCheckoutAction {
+    // Replaces the patterns and updates the working tree to match them
+    Set {
+        #[arg(required = true)]
+        patterns: Vec<String>
+    },
+    List,
+    // Materializes every path again
+    Disable
+}
+
+impl Root {
+    // Without the patterns file, sparse checkout is disabled and every path is materialized
+    pub(super) fn read_sparse_patterns(root_path: &RootPath) -> Result<Option<Pathspec>> {
+        let patterns_path = root_path.gust_join("sparse_checkout");
+        if !patterns_path.as_path().exists() { return Ok(None); }
+        let file = fs::read_to_string(patterns_path.as_path())?;
+        let patterns: Vec<String> = file.lines().filter_map(parse_pattern).collect();
+        Ok(Some(Pathspec::from_root_patterns(&patterns)))
+    }
+
+    // Patterns are matched like pathspecs given at the root: files, folders with everything inside them, or globs
+    pub(super) fn is_materialized(&self, path: &RootRelativePath) -> bool {
+        match &self.sparse_patterns {
+            Some(patterns) => patterns.matches(path),
+            None => true
+        }
+    }
+
+    pub fn sparse_checkout(&mut self, action: &SparseCheckoutAction) -> Result<()> {
+        match action {
+            SparseCheckoutAction::Set { patterns } => {
+                let parsed: Vec<String> = patterns.iter().filter_map(|p| parse_pattern(p)).collect();
+                if parsed.len() != patterns.len() {
+                    return Err(GustError::User("Sparse checkout patterns can't be empty or comments".into()));
+                }
+                self.update_sparse_patterns(Some(Pathspec::from_root_patterns(&parsed)))
+            },
+            SparseCheckoutAction::List => {
+                match &self.sparse_patterns {
+                    Some(patterns) => patterns.args().for_each(|pattern| println!("{}", pattern)),
+                    None => return Err(GustError::User("Sparse checkout is disabled, every path is checked out".into()))
+                }
+                Ok(())
+            },
+            SparseCheckoutAction::Disable => self.update_sparse_patterns(None)
+        }
+    }
+
+    // The working tree is updated before the patterns are saved, so a refused update leaves everything as it was
+    fn update_sparse_patterns(&mut self, patterns: Option<Pathspec>) -> Result<()> {
+        self.ensure_no_changes()?;
+        let old_patterns = std::mem::replace(&mut self.sparse_patterns, patterns);
+        let head_tree = self.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        if let Err(e) = self.apply_changes_to_working_tree(head_tree, false) {
+            self.sparse_patterns = old_patterns;
+            return Err(e);
+        }
+
+        let patterns_path = self.path.gust_join("sparse_checkout");
+        match &self.sparse_patterns {
+            Some(patterns) => {
+                let lines: Vec<String> = patterns.args().map(|p| format!("{}\n", p)).collect();
+                fs::write(patterns_path.as_path(), lines.concat())?;
+            },
+            None if patterns_path.as_path().exists() => fs::remove_file(patterns_path.as_path())?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+// Leading and trailing slashes are optional, "/docs/" and "docs" are the same pattern
+fn parse_pattern(line: &str) -> Option<String> {
+    let pattern = line.trim().trim_matches('/');
+    if pattern.is_empty() || pattern.starts_with('#') { return None; }
+    Some(pattern.to_string())
+}
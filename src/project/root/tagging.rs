@@ -0,0 +1,63 @@
+use super::Root;
+use crate::project::error::{GustError, Result};
+use crate::project::ref_name::{check_ref_lookup, list_ref_names, remove_ref_file, validate_ref_name};
+use crate::project::storable::{ContainsStorePath, ProjectStorable};
+use crate::project::tag::{Annotation, Tag};
+
+impl Root {
+    pub fn tag(&mut self, name: &Option<String>, commit: &Option<String>, annotate: bool, message: &Option<String>, list: bool, delete: bool) -> Result<()> {
+        match name {
+            Some(name) if delete => self.delete_tag(name),
+            Some(name) if !list => {
+                // A message always makes the tag annotated, like in git
+                if annotate && message.is_none() {
+                    return Err(GustError::User("Annotated tags need a message. Use 'gust tag -a <name> -m <message>'".into()));
+                }
+                self.create_tag(name, commit, message)
+            },
+            _ if delete => Err(GustError::User("Specify the tag to delete".into())),
+            _ => self.display_tags()
+        }
+    }
+
+    fn display_tags(&self) -> Result<()> {
+        for tag_name in list_ref_names(self.path.common_join("tags").as_path())? {
+            let tag = Tag::load((self.path.clone(), tag_name))?;
+            match tag.get_annotation(&self.path)? {
+                Some(annotation) => println!("{}    {}", tag.name, annotation.display()),
+                None => println!("{}", tag.name)
+            }
+        }
+        Ok(())
+    }
+
+    fn create_tag(&self, name: &str, commit: &Option<String>, message: &Option<String>) -> Result<()> {
+        validate_ref_name(name, "tag")?;
+        if Tag::build_absolute_path(&(self.path.clone(), name.to_string())).as_path().exists() {
+            return Err(GustError::User(format!("Tag {} already exists", name)));
+        }
+        let commit_id = match commit {
+            Some(revision) => self.resolve_commit_id(revision)?,
+            None => match self.head.get_tree()? {
+                Some(commit_ref) => commit_ref.get_commit_id().to_string(),
+                None => return Err(GustError::User("There are no commits to tag".into()))
+            }
+        };
+
+        let annotation = match message {
+            Some(message) => Some(Annotation::new_annotation(&self.path, &commit_id, name, message)?),
+            None => None
+        };
+        Tag::new(&self.path, name, &commit_id, annotation).save()
+    }
+
+    fn delete_tag(&self, name: &str) -> Result<()> {
+        check_ref_lookup(name, "tag")?;
+        let tag_path = Tag::build_absolute_path(&(self.path.clone(), name.to_string()));
+        if !tag_path.as_path().exists() {
+            return Err(GustError::User(format!("Tag {} not found", name)));
+        }
+        // Like commits, annotation objects are never deleted
+        remove_ref_file(tag_path.as_path(), self.path.common_join("tags").as_path())
+    }
+}
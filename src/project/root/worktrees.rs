@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::Subcommand;
+use super::{Root, RootPath, Head};
+use crate::project::error::{GustError, Result};
+use crate::project::paths::CliPath;
+use crate::project::storable::ProjectStorable;
+
+#[derive(Subcommand)]
+pub(crate) enum WorktreeAction {
+    // Checks out the branch in a new folder that shares this project's objects and branches
+    Add {
+        path: PathBuf,
+        branch: String
+    },
+    List,
+    Remove {
+        path: PathBuf,
+        // Removes it even if it has uncommitted changes or untracked files
+        #[arg(short, long)]
+        force: bool
+    }
+}
+
+const GUST_LINK_PREFIX: &str = "gustdir: ";
+
+// The .gust file of a linked worktree holds the path of its state folder
+pub(super) fn read_gust_link(path: &Path) -> Result<PathBuf> {
+    let content = fs::read_to_string(path)?;
+    match content.trim_end().strip_prefix(GUST_LINK_PREFIX) {
+        Some(gust_dir) => Ok(PathBuf::from(gust_dir)),
+        None => Err(GustError::User(format!("{} isn't a valid worktree link", path.display())))
+    }
+}
+
+impl Root {
+    pub fn worktree(&mut self, action: &WorktreeAction) -> Result<()> {
+        match action {
+            WorktreeAction::Add { path, branch } => self.add_worktree(path, branch),
+            WorktreeAction::List => self.list_worktrees(),
+            WorktreeAction::Remove { path, force } => self.remove_worktree(path, *force)
+        }
+    }
+
+    // A branch can only be checked out in one worktree. Otherwise, committing in one would leave the other's files
+    // behind its HEAD
+    pub(super) fn ensure_not_checked_out_elsewhere(&self, branch_name: &str) -> Result<()> {
+        for worktree in self.worktree_paths()?.into_iter().filter(|w| w.gust_dir != self.path.gust_dir) {
+            if Head::stored_branch_name(&worktree)?.is_some_and(|name| name == branch_name) {
+                return Err(GustError::User(format!("Branch {} is already checked out in worktree {}", branch_name, worktree.path.display())));
+            }
+        }
+        Ok(())
+    }
+
+    fn add_worktree(&mut self, path: &Path, branch_name: &str) -> Result<()> {
+        let absolute_path = CliPath::from(path).to_unchecked_absolute()?;
+        if absolute_path.exists() && fs::read_dir(absolute_path.as_path()).map_or(true, |mut entries| entries.next().is_some()) {
+            return Err(GustError::User(format!("{} already exists and isn't an empty folder", absolute_path.as_path().display())));
+        }
+        let branch = self.load_existing_branch(branch_name)?;
+        if let Head::Attached(current) = &self.head && current.name == branch_name {
+            return Err(GustError::User(format!("Branch {} is already checked out in worktree {}", branch_name, self.path.as_path().display())));
+        }
+        self.ensure_not_checked_out_elsewhere(branch_name)?;
+
+        // The state folder is named after the worktree's folder, with a number if the name is taken
+        let base_name = absolute_path.as_path().file_name()
+            .ok_or_else(|| GustError::User(format!("{} can't be used as a worktree", absolute_path.as_path().display())))?
+            .to_string_lossy().to_string();
+        let mut name = base_name.clone();
+        let mut suffix = 1;
+        while self.path.common_join(format!("worktrees/{}", name)).exists() {
+            suffix += 1;
+            name = format!("{}{}", base_name, suffix);
+        }
+        let gust_dir = self.path.common_join(format!("worktrees/{}", name)).as_path().to_path_buf();
+        fs::create_dir_all(&gust_dir)?;
+        fs::write(gust_dir.join("path"), absolute_path.as_path().display().to_string())?;
+        fs::create_dir_all(absolute_path.as_path())?;
+        fs::write(absolute_path.as_path().join(".gust"), format!("{}{}\n", GUST_LINK_PREFIX, gust_dir.display()))?;
+
+        let root_path = RootPath::linked(absolute_path.as_path().to_path_buf(), gust_dir);
+        Head::Attached(branch).save_to_path(&Head::build_absolute_path(&root_path))?;
+        // HEAD already points at the branch but nothing is on disk, so every file gets written
+        let worktree = Root::open(root_path)?;
+        let tree = worktree.get_last_commit()?.map(|c| c.copy_tree()).unwrap_or_default();
+        worktree.apply_changes_to_working_tree(tree, false)?;
+        worktree.log_checkout(None, "nothing")
+    }
+
+    fn list_worktrees(&self) -> Result<()> {
+        for worktree in self.worktree_paths()? {
+            let head = match Head::stored_branch_name(&worktree)? {
+                Some(name) => format!("[{}]", name),
+                None => "(detached HEAD)".to_string()
+            };
+            let missing = if worktree.path.exists() { "" } else { " (missing)" };
+            println!("{} {}{}", worktree.path.display(), head, missing);
+        }
+        Ok(())
+    }
+
+    fn remove_worktree(&mut self, path: &Path, force: bool) -> Result<()> {
+        let absolute_path = CliPath::from(path).to_unchecked_absolute()?;
+        let mut worktrees = self.worktree_paths()?.into_iter();
+        if worktrees.next().is_some_and(|main| main.path == absolute_path.as_path()) {
+            return Err(GustError::User("The main working tree can't be removed".into()));
+        }
+        let Some(worktree) = worktrees.find(|w| w.path == absolute_path.as_path()) else {
+            return Err(GustError::User(format!("{} isn't a worktree", absolute_path.as_path().display())));
+        };
+        if worktree.gust_dir == self.path.gust_dir {
+            return Err(GustError::User("Can't remove the worktree you're in".into()));
+        }
+
+        // A worktree whose folder was deleted by hand only has its state left
+        if worktree.path.exists() {
+            if !force {
+                let linked = Root::open(worktree.clone())?;
+                if !linked.staging_area.is_empty() || !linked.get_changed_files()?.is_empty() {
+                    return Err(GustError::User(format!(
+                        "Worktree {} has uncommitted changes or untracked files. Use --force to remove it anyway", worktree.path.display()
+                    )));
+                }
+            }
+            fs::remove_dir_all(&worktree.path)?;
+        }
+        fs::remove_dir_all(&worktree.gust_dir)?;
+        Ok(())
+    }
+
+    // The main working tree comes first, then the linked ones sorted by path
+    fn worktree_paths(&self) -> Result<Vec<RootPath>> {
+        let main_path = self.path.common_dir.parent().unwrap_or(&self.path.common_dir).to_path_buf();
+        let mut worktrees = vec![RootPath::main(main_path)];
+        let worktrees_dir = self.path.common_join("worktrees");
+        if worktrees_dir.exists() {
+            let mut linked = Vec::new();
+            for entry in fs::read_dir(worktrees_dir.as_path())? {
+                let gust_dir = entry?.path();
+                let path = PathBuf::from(fs::read_to_string(gust_dir.join("path"))?.trim_end());
+                linked.push(RootPath::linked(path, gust_dir));
+            }
+            linked.sort_by(|a, b| a.path.cmp(&b.path));
+            worktrees.extend(linked);
+        }
+        Ok(worktrees)
+    }
+}
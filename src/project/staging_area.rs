@@ -1,27 +1,62 @@
 use std::borrow::Cow;
 use super::paths::{AbsolutePath, RootRelativePath};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use super::root::RootPath;
 use super::storable::{ContainsStorePath, ProjectStorable};
 use super::error::Result;
+use super::tracked_file::TrackedFile;
 
 #[derive(Debug)]
 pub(super) struct StagingArea {
-    files: HashMap<RootRelativePath, ChangeType>,
+    files: HashMap<RootRelativePath, StagedChange>,
     store_path: AbsolutePath,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredChange")]
+pub(super) struct StagedChange {
+    pub change: ChangeType,
+    // The content to commit, stored as a blob when the file is staged, so later edits aren't committed. Removals
+    // don't have one
+    pub snapshot: Option<TrackedFile>
+}
+
+// Staging areas saved before snapshots existed only hold the change type
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredChange {
+    Staged { change: ChangeType, snapshot: Option<TrackedFile> },
+    ChangeOnly(ChangeType)
+}
+
+impl From<StoredChange> for StagedChange {
+    fn from(stored: StoredChange) -> Self {
+        match stored {
+            StoredChange::Staged { change, snapshot } => Self { change, snapshot },
+            StoredChange::ChangeOnly(change) => Self { change, snapshot: None }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ChangeType {
     Added,
     Modified,
-    Removed
+    // Only the executable bit changed, the content is the same
+    ModeChanged,
+    Removed,
+    // Staged with gust mv. The old path is left out of the commit, it doesn't have an entry of its own
+    Renamed { from: RootRelativePath }
 }
 
 impl StagingArea {
-    pub fn insert(&mut self, path: RootRelativePath, change: ChangeType) -> Result<()> {
-        self.files.insert(path, change);
+    pub fn insert_removal(&mut self, path: RootRelativePath) -> Result<()> {
+        self.files.insert(path, StagedChange { change: ChangeType::Removed, snapshot: None });
+        self.save()
+    }
+    pub fn insert_snapshot(&mut self, path: RootRelativePath, change: ChangeType, snapshot: TrackedFile) -> Result<()> {
+        self.files.insert(path, StagedChange { change, snapshot: Some(snapshot) });
         self.save()
     }
     pub fn remove(&mut self, path: RootRelativePath) -> Result<()> {
@@ -35,7 +70,22 @@ impl StagingArea {
         self.files.contains_key(path)
     }
     pub fn get_files(&self) -> HashMap<RootRelativePath, ChangeType> {
-        self.files.clone()
+        self.files.iter().map(|(path, staged)| (path.clone(), staged.change.clone())).collect()
+    }
+    pub fn get_change(&self, path: &RootRelativePath) -> Option<&ChangeType> {
+        self.files.get(path).map(|staged| &staged.change)
+    }
+    // The paths staged renames move files away from, unless something else was staged there since
+    pub fn rename_sources(&self) -> HashSet<RootRelativePath> {
+        self.files.values()
+            .filter_map(|staged| match &staged.change {
+                ChangeType::Renamed { from } if !self.files.contains_key(from) => Some(from.clone()),
+                _ => None
+            })
+            .collect()
+    }
+    pub fn get_snapshot(&self, path: &RootRelativePath) -> Option<&TrackedFile> {
+        self.files.get(path).and_then(|staged| staged.snapshot.as_ref())
     }
     pub fn clear(&mut self) -> Result<()> {
         self.files.clear();
@@ -45,21 +95,18 @@ impl StagingArea {
 }
 
 impl ProjectStorable for StagingArea {
-    type Stored = HashMap<RootRelativePath, ChangeType>;
+    type Stored = HashMap<RootRelativePath, StagedChange>;
     type CreationArgs = RootPath;
     fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
-        creation_args.join(".gust/staging_area.json")
+        creation_args.gust_join("staging_area.json")
     }
     fn from_stored(mut stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
-        // If a file was modified or added, and now it doesn't exist anymore, remove it from the staging area
-        stored.retain(|path, change_type| {
-            // If I'm staging a removal, then the file won't exist, but I still want the change in the staging area
-            match change_type {
-                ChangeType::Removed => true,
-                _ => {
-                    let absolute_path = creation_args.join(path.as_path());
-                    absolute_path.as_path().exists()
-                }
+        // Entries saved before snapshots existed only point to the file, so they're dropped when it doesn't exist
+        // anymore. Snapshots and removals don't need the file
+        stored.retain(|path, staged| {
+            match (&staged.change, &staged.snapshot) {
+                (ChangeType::Removed, _) | (_, Some(_)) => true,
+                _ => creation_args.join(path.as_path()).exists()
             }
         });
         Ok(Self { files: stored, store_path: Self::build_absolute_path(&creation_args) })
@@ -76,8 +123,8 @@ impl ContainsStorePath for StagingArea {
 }
 
 impl<'a> IntoIterator for &'a StagingArea {
-    type Item = (&'a RootRelativePath, &'a ChangeType);
-    type IntoIter = std::collections::hash_map::Iter<'a, RootRelativePath, ChangeType>;
+    type Item = (&'a RootRelativePath, &'a StagedChange);
+    type IntoIter = std::collections::hash_map::Iter<'a, RootRelativePath, StagedChange>;
     fn into_iter(self) -> Self::IntoIter { self.files.iter() }
 }
 
@@ -86,7 +133,16 @@ impl ChangeType {
         match self {
             ChangeType::Added => "+",
             ChangeType::Modified => "M",
-            ChangeType::Removed => "-"
+            ChangeType::ModeChanged => "X",
+            ChangeType::Removed => "-",
+            ChangeType::Renamed { .. } => "R"
+        }
+    }
+    // Renames also show where the file came from
+    pub fn display_path(&self, path: &RootRelativePath) -> String {
+        match self {
+            ChangeType::Renamed { from } => format!("{} -> {}", from.display(), path.display()),
+            _ => path.display()
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,129 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::fs;
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use super::attributes::Conversion;
+use super::error::Result;
+use super::paths::{AbsolutePath, RootRelativePath};
+use super::root::RootPath;
+use super::storable::{ContainsStorePath, ProjectStorable};
+use super::tracked_file::hash_file;
+
+// Stat data that changes whenever the file's content does. The access time isn't included because reading the file
+// changes it
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub(super) struct FileStat {
+    len: u64,
+    modify_time: SystemTime,
+    // Seconds and nanoseconds, only available on Unix
+    change_time: Option<(i64, i64)>,
+    inode: u64
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(super) struct CacheEntry {
+    stat: FileStat,
+    // The blob depends on .gustattributes too, so entries hashed with other attributes are ignored
+    conversion: Conversion,
+    blob_id: String
+}
+
+// Remembers the blob of every file whose stat data hasn't changed since it was last hashed. It's mutated through
+// shared references because it's only a cache, and filling it doesn't change what the commands do. Files are hashed
+// by several threads, so it's behind a Mutex
+pub(super) struct StatCache {
+    entries: Mutex<HashMap<RootRelativePath, CacheEntry>>,
+    changed: AtomicBool,
+    store_path: AbsolutePath,
+    // The modification time of the cache file when it was loaded
+    saved_at: Option<SystemTime>
+}
+
+impl FileStat {
+    fn from_file(path: &AbsolutePath) -> Result<Self> {
+        let metadata = fs::symlink_metadata(path.as_path())?;
+        #[cfg(unix)]
+        let (change_time, inode) = {
+            use std::os::unix::fs::MetadataExt;
+            (Some((metadata.ctime(), metadata.ctime_nsec())), metadata.ino())
+        };
+        #[cfg(not(unix))]
+        let (change_time, inode) = (None, 0);
+        Ok(Self { len: metadata.len(), modify_time: metadata.modified()?, change_time, inode })
+    }
+}
+
+impl StatCache {
+    // Only hashes the file if its stat data changed since it was cached
+    pub fn blob_id(&self, relative_path: &RootRelativePath, absolute_path: &AbsolutePath, conversion: Conversion) -> Result<String> {
+        let stat = FileStat::from_file(absolute_path)?;
+        if let Some(entry) = self.entries.lock().unwrap().get(relative_path)
+            && entry.stat == stat && entry.conversion == conversion && !self.is_racy(&entry.stat) {
+            return Ok(entry.blob_id.clone());
+        }
+        // The lock isn't held while hashing, so other threads can use the cache
+        let blob_id = hash_file(absolute_path.as_path(), conversion)?;
+        self.insert(relative_path, stat, conversion, &blob_id);
+        Ok(blob_id)
+    }
+
+    // Used after writing or hashing a file for another reason, e.g. by checkout and commit
+    pub fn record(&self, relative_path: &RootRelativePath, absolute_path: &AbsolutePath, conversion: Conversion, blob_id: &str) -> Result<()> {
+        let stat = FileStat::from_file(absolute_path)?;
+        self.insert(relative_path, stat, conversion, blob_id);
+        Ok(())
+    }
+
+    // Drops the entries of files that don't exist anymore
+    pub fn retain(&self, mut keep: impl FnMut(&RootRelativePath) -> bool) {
+        let mut entries = self.entries.lock().unwrap();
+        let count = entries.len();
+        entries.retain(|path, _| keep(path));
+        if entries.len() != count {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn save_if_changed(&self) -> Result<()> {
+        if self.changed.swap(false, Ordering::Relaxed) {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    // Like git does with the index, an entry is only trusted if the file was modified before the cache was saved. A
+    // file modified later could have been written again after it was hashed, within the timestamp's precision, without
+    // changing its stat data. Such entries are hashed again, and saving the cache makes them trusted
+    fn is_racy(&self, stat: &FileStat) -> bool {
+        self.saved_at.is_none_or(|saved_at| stat.modify_time >= saved_at)
+    }
+
+    fn insert(&self, relative_path: &RootRelativePath, stat: FileStat, conversion: Conversion, blob_id: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(relative_path.clone(), CacheEntry { stat, conversion, blob_id: blob_id.to_string() });
+        self.changed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl ProjectStorable for StatCache {
+    type Stored = HashMap<RootRelativePath, CacheEntry>;
+    type CreationArgs = RootPath;
+    fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
+        creation_args.gust_join("index.json")
+    }
+    fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
+        let store_path = Self::build_absolute_path(&creation_args);
+        let saved_at = fs::metadata(store_path.as_path()).and_then(|m| m.modified()).ok();
+        Ok(Self { entries: Mutex::new(stored), changed: AtomicBool::new(false), store_path, saved_at })
+    }
+    fn into_stored(&self) -> Cow<'_, Self::Stored> {
+        Cow::Owned(self.entries.lock().unwrap().clone())
+    }
+}
+
+impl ContainsStorePath for StatCache {
+    fn get_absolute_path(&self) -> &AbsolutePath { &self.store_path }
+}
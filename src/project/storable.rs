@@ -11,6 +11,7 @@ pub trait ProjectStorable: Sized {
     type CreationArgs;
     fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath;
     fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self>;
+    #[allow(clippy::wrong_self_convention)]
     fn into_stored(&self) -> Cow<'_, Self::Stored>;
     fn handle_non_existence(_: &AbsolutePath) -> Result<Self::Stored> {
         Ok(Self::Stored::default())
@@ -40,6 +41,7 @@ pub trait ProjectStorable: Sized {
     fn load(creation_args: Self::CreationArgs) -> Result<Self> { Self::new(creation_args, false) }
 
     fn save_to_path(&self, path: &AbsolutePath) -> Result<()> {
+        create_parent_dirs(path)?;
         let file = fs::File::create(path.as_path())?;
         serde_json::to_writer(file, &self.into_stored())?;
         Ok(())
@@ -49,11 +51,20 @@ pub trait ProjectStorable: Sized {
 pub trait ContainsStorePath: ProjectStorable {
     fn get_absolute_path(&self) -> &AbsolutePath;
     fn save(&self) -> Result<()> {
+        create_parent_dirs(self.get_absolute_path())?;
         let file = fs::File::create(self.get_absolute_path().as_path())?;
         serde_json::to_writer(file, &self.into_stored())?;
         Ok(())
     }
 }
 
+// Names with slashes (e.g. feature/login) are stored in nested folders that may not exist yet
+fn create_parent_dirs(path: &AbsolutePath) -> Result<()> {
+    if let Some(parent) = path.as_path().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
 // The creation of AbsolutePaths from RootPath will be in the implementation of CreationArgs
 // For both RootStorable and IdStorable
\ No newline at end of file
@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+use std::env;
+use serde::{Serialize, Deserialize};
+use crate::project::root::RootPath;
+use super::paths::AbsolutePath;
+use super::storable::{ContainsStorePath, ProjectStorable};
+use super::error::{GustError, Result};
+use super::reflog::current_timestamp;
+
+pub(super) struct Tag {
+    store_path: AbsolutePath,
+    data: StoredTag,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(super) struct StoredTag {
+    commit_id: String,
+    // Id of the annotation object, lightweight tags don't have one
+    annotation: Option<String>
+}
+
+pub(super) struct Annotation {
+    store_path: AbsolutePath,
+    data: StorableAnnotation
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(super) struct StorableAnnotation {
+    commit_id: String,
+    name: String,
+    tagger: String,
+    message: String,
+    timestamp: u64
+}
+
+impl ProjectStorable for Tag {
+    type Stored = StoredTag;
+    type CreationArgs = (RootPath, String);
+    fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
+        creation_args.0.common_join(format!("tags/{}.json", creation_args.1))
+    }
+    fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
+        Ok(Self { store_path: Self::build_absolute_path(&creation_args), data: stored, name: creation_args.1 })
+    }
+    fn into_stored(&self) -> Cow<'_, Self::Stored> {
+        Cow::Borrowed(&self.data)
+    }
+}
+
+impl ContainsStorePath for Tag {
+    fn get_absolute_path(&self) -> &AbsolutePath {
+        &self.store_path
+    }
+}
+
+impl Tag {
+    pub fn new(root_path: &RootPath, name: &str, commit_id: &str, annotation: Option<String>) -> Self {
+        Self {
+            store_path: Self::build_absolute_path(&(root_path.clone(), name.to_string())),
+            data: StoredTag { commit_id: commit_id.to_string(), annotation },
+            name: name.to_string()
+        }
+    }
+    pub fn get_commit_id(&self) -> &str { &self.data.commit_id }
+    pub fn get_annotation(&self, root_path: &RootPath) -> Result<Option<Annotation>> {
+        match &self.data.annotation {
+            Some(id) => Ok(Some(Annotation::load((root_path.clone(), id.clone()))?)),
+            None => Ok(None)
+        }
+    }
+}
+
+impl ProjectStorable for Annotation {
+    type Stored = StorableAnnotation;
+    type CreationArgs = (RootPath, String);
+    fn build_absolute_path(creation_args: &Self::CreationArgs) -> AbsolutePath {
+        creation_args.0.common_join(format!("tag_objects/{}.json", creation_args.1))
+    }
+    fn from_stored(stored: Self::Stored, creation_args: Self::CreationArgs) -> Result<Self> {
+        Ok(Self { store_path: Self::build_absolute_path(&creation_args), data: stored })
+    }
+    fn into_stored(&self) -> Cow<'_, Self::Stored> {
+        Cow::Borrowed(&self.data)
+    }
+    fn handle_non_existence(path: &AbsolutePath) -> Result<Self::Stored> {
+        Err(GustError::ProjectParsing(format!("Tried to load nonexistent tag object at {:?}", path.as_path().display())))
+    }
+}
+
+impl ContainsStorePath for Annotation {
+    fn get_absolute_path(&self) -> &AbsolutePath {
+        &self.store_path
+    }
+}
+
+impl Annotation {
+    // Creates and saves the annotation object, returning its id
+    pub fn new_annotation(root_path: &RootPath, commit_id: &str, name: &str, message: &str) -> Result<String> {
+        let storable = StorableAnnotation {
+            commit_id: commit_id.to_string(),
+            name: name.to_string(),
+            tagger: env::var("USER").unwrap_or_else(|_| "unknown".into()),
+            message: message.to_string(),
+            timestamp: current_timestamp()
+        };
+        let id = sha256::digest(serde_json::to_string(&storable)?);
+        let annotation = Annotation {
+            store_path: Self::build_absolute_path(&(root_path.clone(), id.clone())),
+            data: storable
+        };
+        annotation.save()?;
+        Ok(id)
+    }
+    pub fn display(&self) -> String { format!("{} (tagged by {})", self.data.message, self.data.tagger) }
+}
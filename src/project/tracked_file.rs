@@ -1,57 +1,180 @@
 use std::time::SystemTime;
 use serde::{Serialize, Deserialize};
+use super::attributes::Conversion;
 use super::paths::AbsolutePath;
 use super::error::{Result};
 use std::fs;
 use std::path::Path;
 use crate::project::root::RootPath;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub(super) struct TrackedFile {
     blob_id: String,
-    pub metadata: Metadata
+    pub metadata: Metadata,
+    // Trees stored before modes were tracked don't have it, and their files are treated as regular files
+    #[serde(default)]
+    pub mode: FileMode
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone)]
+// Only the executable bit is tracked, the rest of the permissions depend on the user's umask
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Default, Debug)]
+pub(super) enum FileMode {
+    #[default]
+    Regular,
+    Executable,
+    // The blob holds the link's target instead of the contents of the file it points to
+    Symlink
+}
+
+// What the file looked like when it was committed. Change detection uses the stat cache instead, older trees also
+// have an access_time, which is ignored when they're loaded
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub(super) struct Metadata {
     len: u64,
-    modify_time: SystemTime,
-    access_time: SystemTime
+    modify_time: SystemTime
 }
 
 impl Metadata {
     pub fn new_from_file(path: &AbsolutePath) -> Result<Self> {
-        let metadata = fs::metadata(path.as_path())?;
-        Ok(Self { len: metadata.len(), modify_time: metadata.modified()?, access_time: metadata.accessed()? })
+        let metadata = fs::symlink_metadata(path.as_path())?;
+        Ok(Self { len: metadata.len(), modify_time: metadata.modified()? })
+    }
+}
+
+impl FileMode {
+    #[cfg(unix)]
+    pub fn from_file(path: &AbsolutePath) -> Result<Self> {
+        use std::os::unix::fs::PermissionsExt;
+        let metadata = fs::symlink_metadata(path.as_path())?;
+        Ok(if metadata.is_symlink() {
+            FileMode::Symlink
+        } else if metadata.permissions().mode() & 0o111 != 0 {
+            FileMode::Executable
+        } else {
+            FileMode::Regular
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_file(path: &AbsolutePath) -> Result<Self> {
+        Ok(if path.is_symlink() { FileMode::Symlink } else { FileMode::Regular })
     }
+
+    // Executable files get the execute bit wherever they have the read bit, like chmod +x does with the default umask
+    #[cfg(unix)]
+    pub fn apply_to_file(&self, path: &AbsolutePath) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = fs::metadata(path.as_path())?.permissions();
+        let mode = permissions.mode();
+        permissions.set_mode(match self {
+            FileMode::Regular => mode & !0o111,
+            FileMode::Executable => mode | ((mode & 0o444) >> 2),
+            // Links don't have permissions of their own
+            FileMode::Symlink => return Ok(())
+        });
+        fs::set_permissions(path.as_path(), permissions)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply_to_file(&self, _path: &AbsolutePath) -> Result<()> { Ok(()) }
 }
 
 impl TrackedFile {
-    pub fn new(path: &AbsolutePath, root_dir: &RootPath) -> Result<Self> {
+    // The conversion comes from .gustattributes, and it's applied before hashing so the blob holds the normalized content
+    pub fn new(path: &AbsolutePath, root_dir: &RootPath, conversion: Conversion) -> Result<Self> {
         // Create the blob
-        let hash = hash_file(path.as_path())?;
-        let blob_path = root_dir.join(&format!(".gust/blobs/{}", hash.to_string()));
+        let hash = hash_file(path.as_path(), conversion)?;
+        let blob_path = root_dir.common_join(format!("blobs/{}", hash));
         // Avoids re-copying the file's contents for duplicate files and doesn't raise an error for duplicate files
         if !blob_path.as_path().exists() {
-            fs::copy(path.as_path(), blob_path.as_path())?;
+            if path.is_symlink() || !conversion.is_identity() {
+                fs::write(blob_path.as_path(), blob_content(path.as_path(), conversion)?)?;
+            } else {
+                fs::copy(path.as_path(), blob_path.as_path())?;
+            }
         }
 
         // Create metadata
         let metadata = Metadata::new_from_file(path);
-        if let Err(_) = metadata {
+        if metadata.is_err() {
             fs::remove_file(blob_path.as_path())?;
         }
 
         Ok(Self {
             blob_id: hash,
-            metadata: metadata?
+            metadata: metadata?,
+            mode: FileMode::from_file(path)?
+        })
+    }
+
+    // For content that isn't in any file, like a file with only some of its changes staged. The content is already
+    // normalized, and the metadata is the time it was stored
+    pub fn from_content(content: &[u8], mode: FileMode, root_dir: &RootPath) -> Result<Self> {
+        let hash = sha256::digest(content);
+        let blob_path = root_dir.common_join(format!("blobs/{}", hash));
+        if !blob_path.as_path().exists() {
+            fs::write(blob_path.as_path(), content)?;
+        }
+        Ok(Self {
+            blob_id: hash,
+            metadata: Metadata { len: content.len() as u64, modify_time: SystemTime::now() },
+            mode
         })
     }
 
     pub fn get_blob_id(&self) -> &str { &self.blob_id }
+
+    // The normalized content, or the target of a symlink
+    pub fn read_blob(&self, root_dir: &RootPath) -> Result<Vec<u8>> {
+        Ok(fs::read(root_dir.common_join(format!("blobs/{}", self.blob_id)).as_path())?)
+    }
+
+    // Writes the blob's content to the working tree and sets its mode. fs::copy also copies the
+    // blob's permissions, which come from whichever file created it, so the mode is always set. The conversion turns
+    // normalized line endings back into the ones the file should have on disk
+    pub fn write_to(&self, destination: &AbsolutePath, root_dir: &RootPath, conversion: Conversion) -> Result<()> {
+        let blob_path = root_dir.common_join(format!("blobs/{}", self.blob_id));
+        // Writing to a link would change the file it points to, and a link can't be created over an existing file, so
+        // whatever file or link is there is replaced
+        if fs::symlink_metadata(destination.as_path()).is_ok_and(|m| !m.is_dir()) {
+            fs::remove_file(destination.as_path())?;
+        }
+        if self.mode == FileMode::Symlink {
+            return create_symlink(&fs::read(blob_path.as_path())?, destination.as_path());
+        }
+        if conversion.is_identity() {
+            fs::copy(blob_path.as_path(), destination.as_path())?;
+        } else {
+            fs::write(destination.as_path(), conversion.to_worktree(fs::read(blob_path.as_path())?))?;
+        }
+        self.mode.apply_to_file(destination)
+    }
+}
+
+pub fn hash_file(path: &Path, conversion: Conversion) -> Result<String> {
+    Ok(sha256::digest(blob_content(path, conversion)?))
+}
+
+// A symlink's content is the path it points to, it's never followed or converted
+pub fn blob_content(path: &Path, conversion: Conversion) -> Result<Vec<u8>> {
+    if path.is_symlink() {
+        Ok(fs::read_link(path)?.into_os_string().into_encoded_bytes())
+    } else {
+        Ok(conversion.to_blob(fs::read(path)?))
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &[u8], destination: &Path) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(target), destination)?;
+    Ok(())
 }
 
-pub fn hash_file(path: &Path) -> Result<String> {
-    let file = fs::read(path)?;
-    Ok(sha256::digest(file))
+// Without symlink support the link is checked out as a file holding its target
+#[cfg(not(unix))]
+fn create_symlink(target: &[u8], destination: &Path) -> Result<()> {
+    fs::write(destination, target)?;
+    Ok(())
 }
\ No newline at end of file
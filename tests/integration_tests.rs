@@ -27,8 +27,30 @@ impl TestRepo {
             .expect("Failed to execute gust command")
     }
 
+    // Runs gust and returns what it printed to stdout
+    fn stdout(&self, args: &[&str]) -> String {
+        String::from_utf8_lossy(&self.run_gust(args).stdout).to_string()
+    }
+
+    // Runs gust with the given stdin, for commands that ask questions
+    fn run_gust_with_input(&self, args: &[&str], input: &str, envs: &[(&str, &str)]) -> std::process::Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_Gust"))
+            .current_dir(&self.path)
+            .args(args)
+            .envs(envs.iter().copied())
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .expect("Failed to execute gust command");
+        std::io::Write::write_all(&mut child.stdin.take().unwrap(), input.as_bytes()).unwrap();
+        child.wait_with_output().expect("Failed to wait for gust command")
+    }
+
     fn create_file(&self, name: &str, content: &str) {
-        fs::write(self.path.join(name), content).unwrap();
+        let path = self.path.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
     }
 
     #[allow(dead_code)]
@@ -312,3 +334,1313 @@ fn test_log_shows_commit_history() {
     assert!(log_str.contains("Second commit"));
     assert!(log_str.contains("Third commit"));
 }
+
+#[test]
+fn test_restore_discards_working_tree_changes() {
+    let repo = TestRepo::new("restore_worktree");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "original");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    repo.create_file("file.txt", "modified");
+
+    let output = repo.run_gust(&["restore", "file.txt"]);
+    assert!(output.status.success(), "restore failed");
+    assert_eq!(repo.read_file("file.txt"), "original", "restore should bring back the HEAD version");
+}
+
+#[test]
+fn test_restore_from_source_commit() {
+    let repo = TestRepo::new("restore_source");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    let log_output = repo.run_gust(&["log"]);
+    let log_str = String::from_utf8_lossy(&log_output.stdout);
+    let first_hash: String = log_str
+        .lines()
+        .find(|line| line.contains("First"))
+        .and_then(|line| line.split(':').nth(1))
+        .map(|s| s.trim().chars().take(7).collect())
+        .expect("Could not extract commit hash");
+
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    let output = repo.run_gust(&["restore", "--source", &first_hash, "--staged", "--worktree", "file.txt"]);
+    assert!(output.status.success(), "restore failed");
+    assert_eq!(repo.read_file("file.txt"), "v1", "file should have the source commit's content");
+
+    // HEAD is untouched, and the old version is staged on top of it
+    let log_str = String::from_utf8_lossy(&repo.run_gust(&["log"]).stdout).to_string();
+    assert!(log_str.contains("Commit history of main branch"), "HEAD should not move: {}", log_str);
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status_str.contains("M file.txt"), "restored version should be staged: {}", status_str);
+}
+
+#[test]
+fn test_restore_staged_worktree_leaves_untracked_files_unstaged() {
+    let repo = TestRepo::new("restore_untracked");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("d/file.txt", "v1");
+    repo.run_gust(&["add", "d"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    repo.create_file("d/file.txt", "v2");
+    repo.create_file("d/untracked.txt", "new");
+    let output = repo.run_gust(&["restore", "--staged", "--worktree", "d"]);
+    assert!(output.status.success(), "restore failed");
+
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    let staged_section = status_str.split("Unstaged changes:").next().unwrap();
+    assert!(!staged_section.contains("untracked.txt"), "untracked files shouldn't be staged: {}", status_str);
+    assert_eq!(repo.read_file("d/file.txt"), "v1");
+    assert_eq!(repo.read_file("d/untracked.txt"), "new", "untracked files should be left alone");
+}
+
+#[test]
+fn test_restore_staged_unstages_file() {
+    let repo = TestRepo::new("restore_staged");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "original");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    repo.create_file("file.txt", "modified");
+    repo.run_gust(&["add", "file.txt"]);
+
+    let output = repo.run_gust(&["restore", "--staged", "file.txt"]);
+    assert!(output.status.success(), "restore failed");
+
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    let staged_section = status_str.split("Unstaged changes:").next().unwrap();
+    assert!(!staged_section.contains("file.txt"), "file should no longer be staged: {}", status_str);
+    assert_eq!(repo.read_file("file.txt"), "modified", "--staged should not touch the working tree");
+}
+
+#[test]
+fn test_tag_create_list_and_delete() {
+    let repo = TestRepo::new("tag_lifecycle");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    let output = repo.run_gust(&["tag", "v1.0"]);
+    assert!(output.status.success(), "tag creation failed");
+    assert!(repo.path.join(".gust/tags/v1.0.json").exists(), "tag file was not created");
+
+    repo.run_gust(&["tag", "-a", "v1.1", "-m", "Release 1.1"]);
+    let list_str = String::from_utf8_lossy(&repo.run_gust(&["tag", "-l"]).stdout).to_string();
+    assert!(list_str.contains("v1.0"), "list should show lightweight tag: {}", list_str);
+    assert!(list_str.contains("v1.1") && list_str.contains("Release 1.1"), "list should show annotated tag message: {}", list_str);
+
+    repo.run_gust(&["tag", "-d", "v1.0"]);
+    assert!(!repo.path.join(".gust/tags/v1.0.json").exists(), "tag file should be deleted");
+}
+
+#[test]
+fn test_ref_lookups_stay_inside_the_refs_folder() {
+    let repo = TestRepo::new("ref_traversal");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["tag", "-d", "../branches/main"]).stderr).to_string();
+    assert!(stderr.contains("not a valid tag name"), "the name should be rejected: {}", stderr);
+    assert!(repo.path.join(".gust/branches/main.json").exists(), "the branch should be left in place");
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["branch", "-D", "../tags/x"]).stderr).to_string();
+    assert!(stderr.contains("not a valid branch name"), "{}", stderr);
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["checkout", "../branches/main"]).stderr).to_string();
+    assert!(stderr.contains("not found"), "{}", stderr);
+}
+
+#[test]
+fn test_checkout_tag() {
+    let repo = TestRepo::new("tag_checkout");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["tag", "release"]);
+
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    let output = repo.run_gust(&["checkout", "release"]);
+    assert!(output.status.success(), "checkout tag failed");
+    assert_eq!(repo.read_file("file.txt"), "v1", "file should have the tagged content");
+
+    let branch_str = String::from_utf8_lossy(&repo.run_gust(&["branch"]).stdout).to_string();
+    assert!(branch_str.contains("HEAD attached at release"), "HEAD should be detached at the tag: {}", branch_str);
+}
+
+#[test]
+fn test_branch_delete_refuses_unmerged_commits() {
+    let repo = TestRepo::new("branch_delete");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    repo.run_gust(&["branch", "merged"]);
+    repo.run_gust(&["branch", "feature"]);
+    repo.run_gust(&["checkout", "feature"]);
+    repo.create_file("file.txt", "feature work");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Feature"]);
+
+    // The checked out branch can't be deleted
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["branch", "-d", "feature"]).stderr).to_string();
+    assert!(stderr.contains("checked out"), "deleting the current branch should fail: {}", stderr);
+
+    repo.run_gust(&["checkout", "main"]);
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["branch", "-d", "feature"]).stderr).to_string();
+    assert!(stderr.contains("gust branch -D"), "unmerged branch should not be deleted: {}", stderr);
+    assert!(repo.path.join(".gust/branches/feature.json").exists());
+
+    repo.run_gust(&["branch", "-d", "merged"]);
+    assert!(!repo.path.join(".gust/branches/merged.json").exists(), "merged branch should be deleted");
+
+    repo.run_gust(&["branch", "-D", "feature"]);
+    assert!(!repo.path.join(".gust/branches/feature.json").exists(), "-D should force the deletion");
+}
+
+#[test]
+fn test_branch_rename_updates_head() {
+    let repo = TestRepo::new("branch_rename");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    let output = repo.run_gust(&["branch", "-m", "main", "trunk"]);
+    assert!(output.status.success(), "rename failed");
+    assert!(!repo.path.join(".gust/branches/main.json").exists());
+    assert!(repo.path.join(".gust/branches/trunk.json").exists());
+
+    let branch_str = String::from_utf8_lossy(&repo.run_gust(&["branch"]).stdout).to_string();
+    assert!(branch_str.contains("* trunk"), "HEAD should follow the renamed branch: {}", branch_str);
+}
+
+#[test]
+fn test_branch_rename_updates_upstreams() {
+    let repo = TestRepo::new("branch_rename_upstream");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+    repo.run_gust(&["branch", "feature"]);
+    repo.run_gust(&["branch", "--set-upstream-to", "main", "feature"]);
+
+    let output = repo.run_gust(&["branch", "-m", "main", "trunk"]);
+    assert!(output.status.success(), "rename failed");
+
+    let branch_str = String::from_utf8_lossy(&repo.run_gust(&["branch", "-vv"]).stdout).to_string();
+    assert!(branch_str.contains("feature [trunk: ahead 0, behind 0]"), "the upstream should follow the rename: {}", branch_str);
+}
+
+#[test]
+fn test_reflog_records_commits_and_resolves_refs() {
+    let repo = TestRepo::new("reflog");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    let reflog_str = String::from_utf8_lossy(&repo.run_gust(&["reflog", "main"]).stdout).to_string();
+    let lines: Vec<&str> = reflog_str.lines().collect();
+    assert_eq!(lines.len(), 2, "reflog should have one entry per commit: {}", reflog_str);
+    assert!(lines[0].contains("main@{0}: commit: Second"), "newest entry should come first: {}", reflog_str);
+    assert!(lines[1].contains("main@{1}: commit: First"), "oldest entry should come last: {}", reflog_str);
+
+    let output = repo.run_gust(&["checkout", "main@{1}"]);
+    assert!(output.status.success(), "checkout of a reflog reference failed");
+    assert_eq!(repo.read_file("file.txt"), "v1", "main@{{1}} should resolve to the first commit");
+}
+
+#[test]
+fn test_reflog_recovers_detached_commits() {
+    let repo = TestRepo::new("reflog_detached");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["checkout", "HEAD@{0}"]);
+
+    repo.create_file("file.txt", "detached work");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Detached"]);
+    repo.run_gust(&["checkout", "main"]);
+    assert_eq!(repo.read_file("file.txt"), "v1");
+
+    let reflog_str = String::from_utf8_lossy(&repo.run_gust(&["reflog"]).stdout).to_string();
+    assert!(reflog_str.contains("HEAD@{1}: commit: Detached"), "detached commit should be in the HEAD reflog: {}", reflog_str);
+
+    repo.run_gust(&["checkout", "HEAD@{1}"]);
+    assert_eq!(repo.read_file("file.txt"), "detached work", "detached commit should be recoverable");
+}
+
+#[test]
+fn test_revision_ancestors_and_ranges() {
+    let repo = TestRepo::new("revision_syntax");
+
+    repo.run_gust(&["init"]);
+    for (content, message) in [("v1", "First"), ("v2", "Second"), ("v3", "Third")] {
+        repo.create_file("file.txt", content);
+        repo.run_gust(&["add", "file.txt"]);
+        repo.run_gust(&["commit", "-m", message]);
+    }
+
+    let log_str = String::from_utf8_lossy(&repo.run_gust(&["log", "HEAD~2..main"]).stdout).to_string();
+    assert!(log_str.contains("Third") && log_str.contains("Second"), "range should include newer commits: {}", log_str);
+    assert!(!log_str.contains("First"), "range should exclude the base's history: {}", log_str);
+
+    let output = repo.run_gust(&["branch", "old", "main^^"]);
+    assert!(output.status.success(), "branch creation at a start point failed");
+    let log_str = String::from_utf8_lossy(&repo.run_gust(&["log", "old"]).stdout).to_string();
+    assert!(log_str.contains("First") && !log_str.contains("Second"), "branch should start at main^^: {}", log_str);
+
+    repo.run_gust(&["checkout", "HEAD~1"]);
+    assert_eq!(repo.read_file("file.txt"), "v2", "HEAD~1 should resolve to the second commit");
+}
+
+#[test]
+fn test_ambiguous_revision_lists_candidates() {
+    let repo = TestRepo::new("revision_ambiguous");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+    repo.run_gust(&["branch", "release"]);
+    repo.run_gust(&["tag", "release"]);
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["log", "release"]).stderr).to_string();
+    assert!(stderr.contains("branch release") && stderr.contains("tag release"), "error should list the candidates: {}", stderr);
+}
+
+#[test]
+fn test_hierarchical_branch_names() {
+    let repo = TestRepo::new("branch_hierarchical");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    let output = repo.run_gust(&["branch", "feature/login"]);
+    assert!(output.status.success(), "nested branch creation failed");
+    assert!(repo.path.join(".gust/branches/feature/login.json").exists(), "nested branch file was not created");
+
+    repo.run_gust(&["checkout", "feature/login"]);
+    let branch_str = String::from_utf8_lossy(&repo.run_gust(&["branch"]).stdout).to_string();
+    assert!(branch_str.contains("* feature/login"), "nested branch should be listed as current: {}", branch_str);
+
+    repo.run_gust(&["checkout", "main"]);
+    repo.run_gust(&["branch", "-d", "feature/login"]);
+    assert!(!repo.path.join(".gust/branches/feature").exists(), "empty namespace folder should be removed");
+}
+
+#[test]
+fn test_invalid_ref_names_are_rejected() {
+    let repo = TestRepo::new("ref_name_validation");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    for name in ["..", "DETACHED_HEAD", "has space", "a~1", "trailing/", "x/../y"] {
+        let stderr = String::from_utf8_lossy(&repo.run_gust(&["branch", name]).stderr).to_string();
+        assert!(stderr.contains("not a valid branch name"), "{:?} should be rejected: {}", name, stderr);
+    }
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["tag", "bad:tag"]).stderr).to_string();
+    assert!(stderr.contains("not a valid tag name"), "tags should be validated too: {}", stderr);
+}
+
+#[test]
+fn test_upstream_ahead_behind() {
+    let repo = TestRepo::new("branch_upstream");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    repo.run_gust(&["branch", "feature"]);
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Main work"]);
+
+    repo.run_gust(&["checkout", "feature"]);
+    let output = repo.run_gust(&["branch", "--set-upstream-to", "main"]);
+    assert!(output.status.success(), "setting the upstream failed");
+    for (content, message) in [("f1", "Feature 1"), ("f2", "Feature 2")] {
+        repo.create_file("other.txt", content);
+        repo.run_gust(&["add", "other.txt"]);
+        repo.run_gust(&["commit", "-m", message]);
+    }
+
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status_str.contains("main: ahead 2, behind 1"), "status should show ahead/behind counts: {}", status_str);
+
+    let branch_str = String::from_utf8_lossy(&repo.run_gust(&["branch", "-vv"]).stdout).to_string();
+    assert!(branch_str.contains("* feature [main: ahead 2, behind 1]"), "branch -vv should show the upstream: {}", branch_str);
+}
+
+#[test]
+fn test_switch_create_at_start_point() {
+    let repo = TestRepo::new("switch_create");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    let output = repo.run_gust(&["switch", "-c", "hotfix", "HEAD~1"]);
+    assert!(output.status.success(), "switch -c failed");
+    assert_eq!(repo.read_file("file.txt"), "v1", "working tree should be at the start point");
+    let branch_str = String::from_utf8_lossy(&repo.run_gust(&["branch"]).stdout).to_string();
+    assert!(branch_str.contains("* hotfix"), "HEAD should be attached to the new branch: {}", branch_str);
+
+    // A failed switch doesn't leave the new branch behind
+    repo.create_file("file.txt", "uncommitted");
+    repo.run_gust(&["switch", "-c", "other"]);
+    assert!(!repo.path.join(".gust/branches/other.json").exists(), "branch should not be created when the switch fails");
+}
+
+#[test]
+fn test_switch_removes_created_branch_when_refused() {
+    let repo = TestRepo::new("switch_refused");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.create_file("extra.txt", "tracked");
+    repo.run_gust(&["add", "extra.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+    repo.run_gust(&["switch", "-c", "hotfix", "HEAD~1"]);
+
+    repo.create_file("extra.txt", "untracked");
+    let output = repo.run_gust(&["switch", "-c", "other", "main"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("would be overwritten"), "switch should be refused");
+    assert!(!repo.path.join(".gust/branches/other.json").exists(), "the created branch should be deleted again");
+    assert_eq!(repo.read_file("extra.txt"), "untracked");
+
+    let output = repo.run_gust(&["switch", "main", "HEAD~1"]);
+    assert!(!output.status.success(), "a start point without -c should be rejected");
+}
+
+#[test]
+fn test_switch_orphan() {
+    let repo = TestRepo::new("switch_orphan");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Initial"]);
+
+    let output = repo.run_gust(&["switch", "--orphan", "docs"]);
+    assert!(output.status.success(), "switch --orphan failed");
+    assert!(!repo.file_exists("file.txt"), "orphan branch should start with an empty tree");
+
+    let log_str = String::from_utf8_lossy(&repo.run_gust(&["log"]).stdout).to_string();
+    assert!(!log_str.contains("Initial"), "orphan branch should have no history: {}", log_str);
+}
+
+#[test]
+fn test_checkout_keeps_untracked_files() {
+    let repo = TestRepo::new("checkout_untracked");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["branch", "feature"]);
+
+    repo.create_file("notes.txt", "my notes");
+    let output = repo.run_gust(&["checkout", "feature"]);
+    assert!(output.status.success(), "checkout with untracked files failed");
+    assert_eq!(repo.read_file("notes.txt"), "my notes", "untracked file should survive the checkout");
+}
+
+#[test]
+fn test_checkout_refuses_to_overwrite_untracked_files() {
+    let repo = TestRepo::new("checkout_overwrite");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["switch", "-c", "feature"]);
+    repo.create_file("config.txt", "tracked on feature");
+    repo.run_gust(&["add", "config.txt"]);
+    repo.run_gust(&["commit", "-m", "Add config"]);
+    repo.run_gust(&["checkout", "main"]);
+
+    repo.create_file("config.txt", "local untracked config");
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["checkout", "feature"]).stderr).to_string();
+    assert!(stderr.contains("would be overwritten") && stderr.contains("config.txt"), "checkout should be refused: {}", stderr);
+    assert_eq!(repo.read_file("config.txt"), "local untracked config");
+
+    repo.run_gust(&["checkout", "--force", "feature"]);
+    assert_eq!(repo.read_file("config.txt"), "tracked on feature", "--force should overwrite the untracked file");
+}
+
+#[test]
+fn test_checkout_force_discards_staged_changes() {
+    let repo = TestRepo::new("checkout_force_staged");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["branch", "feature"]);
+
+    repo.create_file("file.txt", "staged on main");
+    repo.run_gust(&["add", "file.txt"]);
+    let output = repo.run_gust(&["checkout", "--force", "feature"]);
+    assert!(output.status.success(), "forced checkout failed");
+    assert_eq!(repo.read_file("file.txt"), "v1");
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["commit", "-m", "Leftover"]).stderr).to_string();
+    assert!(stderr.contains("Staged changes not found"), "the staged change should be discarded: {}", stderr);
+}
+
+#[test]
+fn test_checkout_only_rewrites_changed_files() {
+    let repo = TestRepo::new("checkout_incremental");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("same.txt", "unchanged");
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "same.txt", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["branch", "feature"]);
+
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    let modified_before = fs::metadata(repo.path.join("same.txt")).unwrap().modified().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    repo.run_gust(&["checkout", "feature"]);
+
+    assert_eq!(repo.read_file("file.txt"), "v1");
+    let modified_after = fs::metadata(repo.path.join("same.txt")).unwrap().modified().unwrap();
+    assert_eq!(modified_before, modified_after, "files with the same blob should not be rewritten");
+
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(!status_str.contains("file.txt"), "checked out files should be clean: {}", status_str);
+}
+
+#[test]
+fn test_checkout_creates_and_removes_directories() {
+    let repo = TestRepo::new("checkout_directories");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["switch", "-c", "feature"]);
+    repo.create_file("src/deep/file.rs", "fn main() {}");
+    repo.run_gust(&["add", "src/deep/file.rs"]);
+    repo.run_gust(&["commit", "-m", "Add deep file"]);
+
+    repo.run_gust(&["checkout", "main"]);
+    assert!(!repo.path.join("src").exists(), "folders left empty by the checkout should be removed");
+
+    repo.run_gust(&["checkout", "feature"]);
+    assert_eq!(repo.read_file("src/deep/file.rs"), "fn main() {}", "missing parent folders should be created");
+}
+
+#[test]
+fn test_checkout_file_directory_transitions() {
+    let repo = TestRepo::new("checkout_file_directory");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("docs", "a single file");
+    repo.run_gust(&["add", "docs"]);
+    repo.run_gust(&["commit", "-m", "Docs as a file"]);
+    repo.run_gust(&["switch", "-c", "feature"]);
+    fs::remove_file(repo.path.join("docs")).unwrap();
+    repo.create_file("docs/index.md", "index");
+    repo.run_gust(&["add", "."]);
+    repo.run_gust(&["commit", "-m", "Docs as a folder"]);
+
+    repo.run_gust(&["checkout", "main"]);
+    assert_eq!(repo.read_file("docs"), "a single file", "the folder should be replaced by the file");
+
+    repo.run_gust(&["checkout", "feature"]);
+    assert_eq!(repo.read_file("docs/index.md"), "index", "the file should be replaced by the folder");
+
+    // An untracked file inside the folder would be lost, so the checkout is refused
+    repo.create_file("docs/draft.md", "draft");
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["checkout", "main"]).stderr).to_string();
+    assert!(stderr.contains("would be overwritten") && stderr.contains("docs/draft.md"), "checkout should be refused: {}", stderr);
+    assert_eq!(repo.read_file("docs/draft.md"), "draft");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_executable_bit_is_tracked() {
+    use std::os::unix::fs::PermissionsExt;
+    let repo = TestRepo::new("executable_bit");
+    let is_executable = |name: &str| fs::metadata(repo.path.join(name)).unwrap().permissions().mode() & 0o111 != 0;
+    let set_mode = |name: &str, mode: u32| fs::set_permissions(repo.path.join(name), fs::Permissions::from_mode(mode)).unwrap();
+
+    repo.run_gust(&["init"]);
+    repo.create_file("build.sh", "#!/bin/sh\necho build");
+    set_mode("build.sh", 0o755);
+    repo.run_gust(&["add", "build.sh"]);
+    repo.run_gust(&["commit", "-m", "Add build script"]);
+    repo.run_gust(&["branch", "feature"]);
+
+    // Changing only the mode is reported by status
+    set_mode("build.sh", 0o644);
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status_str.contains("X build.sh"), "mode change should be shown: {}", status_str);
+    repo.run_gust(&["add", "build.sh"]);
+    repo.run_gust(&["commit", "-m", "Not executable"]);
+
+    repo.run_gust(&["checkout", "feature"]);
+    assert!(is_executable("build.sh"), "checkout should restore the executable bit");
+    repo.run_gust(&["checkout", "main"]);
+    assert!(!is_executable("build.sh"), "checkout should clear the executable bit");
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(!status_str.contains("build.sh"), "checked out file should be clean: {}", status_str);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinks_are_tracked_as_links() {
+    use std::os::unix::fs::symlink;
+    let repo = TestRepo::new("symlinks");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("data/big.txt", "lots of data");
+    symlink("data", repo.path.join("data_link")).unwrap();
+    symlink("missing.txt", repo.path.join("dangling")).unwrap();
+    repo.run_gust(&["add", "."]);
+    let output = repo.run_gust(&["commit", "-m", "Add links"]);
+    assert!(output.status.success() && output.stderr.is_empty(), "commit with symlinks failed: {}", String::from_utf8_lossy(&output.stderr));
+    repo.run_gust(&["branch", "feature"]);
+
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(!status_str.contains("data_link/") && !status_str.contains("dangling"), "links shouldn't be followed: {}", status_str);
+
+    // Retargeting the link is a change, even though nothing it points to changed
+    fs::remove_file(repo.path.join("dangling")).unwrap();
+    symlink("data/big.txt", repo.path.join("dangling")).unwrap();
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status_str.contains("M dangling"), "retargeted link should be modified: {}", status_str);
+    repo.run_gust(&["add", "dangling"]);
+    repo.run_gust(&["commit", "-m", "Retarget"]);
+
+    repo.run_gust(&["checkout", "feature"]);
+    assert_eq!(fs::read_link(repo.path.join("dangling")).unwrap(), std::path::PathBuf::from("missing.txt"), "checkout should recreate the link");
+    assert_eq!(fs::read_link(repo.path.join("data_link")).unwrap(), std::path::PathBuf::from("data"));
+    assert_eq!(repo.read_file("data/big.txt"), "lots of data", "writing the link shouldn't touch its target");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_checkout_replaces_files_and_links() {
+    use std::os::unix::fs::symlink;
+    let repo = TestRepo::new("checkout_links");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("target.txt", "target");
+    repo.create_file("entry", "a regular file");
+    symlink("missing.txt", repo.path.join("dangling")).unwrap();
+    repo.run_gust(&["add", "."]);
+    repo.run_gust(&["commit", "-m", "File"]);
+    repo.run_gust(&["branch", "plain"]);
+
+    fs::remove_file(repo.path.join("entry")).unwrap();
+    symlink("target.txt", repo.path.join("entry")).unwrap();
+    repo.run_gust(&["add", "entry"]);
+    repo.run_gust(&["rm", "dangling"]);
+    repo.run_gust(&["commit", "-m", "Link"]);
+
+    let output = repo.run_gust(&["checkout", "plain"]);
+    assert!(output.status.success() && output.stderr.is_empty(), "link to file checkout failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(!repo.path.join("entry").is_symlink(), "the link should be replaced by the file");
+    assert_eq!(repo.read_file("entry"), "a regular file");
+    assert_eq!(repo.read_file("target.txt"), "target", "replacing the link shouldn't touch its target");
+
+    let output = repo.run_gust(&["checkout", "main"]);
+    assert!(output.status.success() && output.stderr.is_empty(), "file to link checkout failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read_link(repo.path.join("entry")).unwrap(), std::path::PathBuf::from("target.txt"), "the file should be replaced by the link");
+    assert!(fs::symlink_metadata(repo.path.join("dangling")).is_err(), "the dangling link should be deleted");
+    let branch_str = String::from_utf8_lossy(&repo.run_gust(&["branch"]).stdout).to_string();
+    assert!(branch_str.contains("* main"), "HEAD should move to main: {}", branch_str);
+}
+
+#[test]
+fn test_sparse_checkout() {
+    let repo = TestRepo::new("sparse_checkout");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("app/main.rs", "fn main() {}");
+    repo.create_file("assets/huge.bin", "huge");
+    repo.create_file("README.md", "readme");
+    repo.run_gust(&["add", "."]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    repo.run_gust(&["sparse-checkout", "set", "app", "README.md"]);
+    assert!(!repo.file_exists("assets"), "paths outside the patterns should be removed");
+    assert_eq!(repo.read_file("app/main.rs"), "fn main() {}");
+    let list_str = String::from_utf8_lossy(&repo.run_gust(&["sparse-checkout", "list"]).stdout).to_string();
+    assert!(list_str.contains("app") && list_str.contains("README.md"), "patterns should be listed: {}", list_str);
+
+    // Missing paths outside the patterns aren't removals, and commits keep them
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(!status_str.contains("assets"), "unmaterialized paths shouldn't be reported: {}", status_str);
+    repo.create_file("app/main.rs", "fn main() { run() }");
+    repo.run_gust(&["add", "."]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    repo.run_gust(&["sparse-checkout", "disable"]);
+    assert_eq!(repo.read_file("assets/huge.bin"), "huge", "commits should carry unmaterialized files forward");
+    assert_eq!(repo.read_file("app/main.rs"), "fn main() { run() }");
+
+    // Globs are matched like pathspecs
+    repo.run_gust(&["sparse-checkout", "set", "*.md", "app/*.rs"]);
+    assert!(!repo.file_exists("assets"), "paths outside the globs should be removed");
+    assert_eq!(repo.read_file("README.md"), "readme");
+    assert_eq!(repo.read_file("app/main.rs"), "fn main() { run() }");
+}
+
+#[test]
+fn test_worktrees_share_objects_and_branches() {
+    let repo = TestRepo::new("worktrees_main");
+    let linked = TestRepo::new("worktrees_linked");
+    let linked_path = linked.path.to_str().unwrap();
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["branch", "feature"]);
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["worktree", "add", linked_path, "main"]).stderr).to_string();
+    assert!(stderr.contains("already checked out"), "a branch can't be in two worktrees: {}", stderr);
+    repo.run_gust(&["worktree", "add", linked_path, "feature"]);
+    assert_eq!(linked.read_file("file.txt"), "v1", "the branch should be checked out in the worktree");
+    let status_str = String::from_utf8_lossy(&linked.run_gust(&["status"]).stdout).to_string();
+    assert!(!status_str.contains(".gust") && !status_str.contains("+"), "a new worktree should be clean: {}", status_str);
+
+    // Commits made in the worktree are visible from the main project
+    linked.create_file("feature.txt", "feature work");
+    linked.run_gust(&["add", "feature.txt"]);
+    linked.run_gust(&["commit", "-m", "Feature work"]);
+    let log_str = String::from_utf8_lossy(&repo.run_gust(&["log", "feature"]).stdout).to_string();
+    assert!(log_str.contains("Feature work"), "branches should be shared: {}", log_str);
+    assert!(!repo.file_exists("feature.txt"), "each worktree has its own files");
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["checkout", "feature"]).stderr).to_string();
+    assert!(stderr.contains("already checked out"), "checkout should refuse a branch used by another worktree: {}", stderr);
+    let list_str = String::from_utf8_lossy(&repo.run_gust(&["worktree", "list"]).stdout).to_string();
+    assert!(list_str.contains("[main]") && list_str.contains(&format!("{} [feature]", linked_path)), "worktrees should be listed: {}", list_str);
+}
+
+#[test]
+fn test_worktree_remove() {
+    let repo = TestRepo::new("worktree_remove_main");
+    let linked = TestRepo::new("worktree_remove_linked");
+    let linked_path = linked.path.to_str().unwrap();
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["branch", "feature"]);
+    repo.run_gust(&["worktree", "add", linked_path, "feature"]);
+
+    linked.create_file("scratch.txt", "untracked");
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["worktree", "remove", linked_path]).stderr).to_string();
+    assert!(stderr.contains("--force"), "worktrees with untracked files need --force: {}", stderr);
+    repo.run_gust(&["worktree", "remove", "--force", linked_path]);
+    assert!(!linked.path.exists(), "the worktree folder should be deleted");
+
+    // The branch is free again once its worktree is gone
+    let output = repo.run_gust(&["checkout", "feature"]);
+    assert!(output.stderr.is_empty(), "checkout failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_line_endings_are_normalized() {
+    let repo = TestRepo::new("line_endings");
+
+    repo.run_gust(&["init"]);
+    repo.create_file(".gustattributes", "*.txt text\n*.bat eol=crlf\n*.bin binary\n");
+    repo.create_file("notes.txt", "one\r\ntwo\r\n");
+    repo.create_file("run.bat", "echo one\necho two\n");
+    repo.create_file("data.bin", "raw\r\nbytes");
+    repo.run_gust(&["add", "."]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["branch", "feature"]);
+
+    // Switching line endings isn't a change, since the normalized content is the same
+    repo.create_file("notes.txt", "one\ntwo\n");
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(!status_str.contains("notes.txt"), "line endings alone shouldn't be a change: {}", status_str);
+    repo.create_file("data.bin", "raw\nbytes");
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status_str.contains("M data.bin"), "binary files aren't normalized: {}", status_str);
+
+    repo.create_file("data.bin", "raw\r\nbytes");
+    repo.create_file("run.bat", "echo three\n");
+    repo.run_gust(&["add", "."]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+    repo.run_gust(&["checkout", "feature"]);
+    assert_eq!(repo.read_file("run.bat"), "echo one\r\necho two\r\n", "eol=crlf should be applied on checkout");
+    assert_eq!(repo.read_file("notes.txt"), "one\ntwo\n", "text files are checked out with LF");
+    assert_eq!(repo.read_file("data.bin"), "raw\r\nbytes", "binary files are checked out untouched");
+}
+
+#[test]
+fn test_stat_cache_tracks_file_changes() {
+    let repo = TestRepo::new("stat_cache");
+    let set_old_mtime = |name: &str| {
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(repo.path.join(name)).unwrap()
+            .set_times(fs::FileTimes::new().set_modified(old)).unwrap();
+    };
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    set_old_mtime("file.txt");
+    repo.run_gust(&["status"]);
+    let index = repo.read_file(".gust/index.json");
+    assert!(index.contains("file.txt"), "status should cache the file's blob: {}", index);
+
+    // Same size and modification time, but the change time gives the edit away
+    repo.create_file("file.txt", "v2");
+    set_old_mtime("file.txt");
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status_str.contains("M file.txt"), "a cached file that changed should be rehashed: {}", status_str);
+}
+
+#[test]
+fn test_stat_cache_serves_checked_out_files() {
+    let repo = TestRepo::new("stat_cache_checkout");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["branch", "feature"]);
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+    repo.run_gust(&["checkout", "feature"]);
+
+    // Swapping the cached blob shows whether status used the entry or hashed the file
+    let index = repo.read_file(".gust/index.json");
+    let real_blob = sha256::digest("v1");
+    assert!(index.contains(&real_blob), "checkout should record the file it wrote: {}", index);
+    // The rewritten cache has to be newer than the file even with coarse timestamps
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    repo.create_file(".gust/index.json", &index.replace(&real_blob, &sha256::digest("bogus")));
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status_str.contains("M file.txt"), "the file should be served from the cache: {}", status_str);
+
+    // A file modified after the cache was saved could have changed without changing its stat data, so it's hashed
+    let future = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+    fs::File::options().write(true).open(repo.path.join("file.txt")).unwrap()
+        .set_times(fs::FileTimes::new().set_modified(future)).unwrap();
+    repo.create_file(".gust/index.json", &repo.read_file(".gust/index.json").replace(&real_blob, &sha256::digest("bogus")));
+    let status_str = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(!status_str.contains("M file.txt"), "a racy entry should be hashed again: {}", status_str);
+}
+
+#[test]
+fn test_status_is_deterministic_with_any_job_count() {
+    let repo = TestRepo::new("parallel_status");
+
+    repo.run_gust(&["init"]);
+    for i in 0..20 {
+        repo.create_file(&format!("dir{}/file{}.txt", i % 4, i), &format!("content {}", i));
+    }
+    repo.run_gust(&["add", "dir0", "dir1"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    for i in (0..20).step_by(3) {
+        repo.create_file(&format!("dir{}/file{}.txt", i % 4, i), "changed");
+    }
+
+    let serial = String::from_utf8_lossy(&repo.run_gust(&["-j", "1", "status"]).stdout).to_string();
+    let parallel = String::from_utf8_lossy(&repo.run_gust(&["status", "--jobs", "8"]).stdout).to_string();
+    assert_eq!(serial, parallel, "the job count shouldn't change the output");
+    let lines: Vec<&str> = serial.lines().filter(|line| line.contains("dir")).collect();
+    let mut sorted = lines.clone();
+    sorted.sort_by_key(|line| line[4..].to_string());
+    assert_eq!(lines, sorted, "changes should be sorted by path");
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["-j", "0", "status"]).stderr).to_string();
+    assert!(stderr.contains("invalid value"), "at least one job is needed: {}", stderr);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_fsmonitor_status_matches_full_scan() {
+    let repo = TestRepo::new("fsmonitor");
+    let status = |repo: &TestRepo| String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+
+    repo.run_gust(&["init"]);
+    repo.create_file("kept.txt", "kept");
+    repo.create_file("removed.txt", "removed");
+    repo.create_file("dir/edited.txt", "v1");
+    repo.run_gust(&["add", "kept.txt", "removed.txt", "dir"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    let start = String::from_utf8_lossy(&repo.run_gust(&["fsmonitor", "start"]).stdout).to_string();
+    assert!(start.contains("Started"), "the daemon should start: {}", start);
+    let running = String::from_utf8_lossy(&repo.run_gust(&["fsmonitor", "status"]).stdout).to_string();
+    assert!(running.contains("is running"), "{}", running);
+    // The first query scans everything, later ones only read what changed
+    status(&repo);
+
+    repo.create_file("dir/edited.txt", "v2");
+    fs::remove_file(repo.path.join("removed.txt")).unwrap();
+    repo.create_file("new/nested/file.txt", "new");
+    let monitored = status(&repo);
+    assert!(monitored.contains("M dir/edited.txt"), "{}", monitored);
+    assert!(monitored.contains("- removed.txt"), "{}", monitored);
+    assert!(monitored.contains("new/nested/file.txt"), "files in new folders should be found: {}", monitored);
+
+    repo.run_gust(&["fsmonitor", "stop"]);
+    let stopped = String::from_utf8_lossy(&repo.run_gust(&["fsmonitor", "status"]).stdout).to_string();
+    assert!(stopped.contains("isn't running"), "{}", stopped);
+    assert_eq!(monitored, status(&repo), "a full scan should find the same changes");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_fsmonitor_skips_untouched_files() {
+    let repo = TestRepo::new("fsmonitor_untouched");
+    let status = |repo: &TestRepo| String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+
+    repo.run_gust(&["init"]);
+    repo.create_file("edited.txt", "v1");
+    repo.create_file("untouched.txt", "untouched");
+    repo.run_gust(&["add", "edited.txt", "untouched.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["fsmonitor", "start"]);
+    status(&repo);
+
+    // A bogus cached blob would show up as a change if the file was looked at
+    let index = repo.read_file(".gust/index.json");
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    repo.create_file(".gust/index.json", &index.replace(&sha256::digest("untouched"), &sha256::digest("bogus")));
+    repo.create_file("edited.txt", "v2");
+    let monitored = status(&repo);
+    assert!(monitored.contains("M edited.txt"), "{}", monitored);
+    assert!(!monitored.contains("untouched.txt"), "untouched files shouldn't be examined: {}", monitored);
+    let monitored = status(&repo);
+    assert!(monitored.contains("M edited.txt"), "changes should still be shown once the daemon stops reporting them: {}", monitored);
+
+    repo.run_gust(&["fsmonitor", "stop"]);
+    let scanned = status(&repo);
+    assert!(scanned.contains("M untouched.txt"), "a full scan examines every file: {}", scanned);
+}
+
+#[test]
+fn test_add_patch_stages_selected_hunks() {
+    let repo = TestRepo::new("add_patch");
+    let lines: Vec<String> = (1..=12).map(|i| format!("line {}", i)).collect();
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", &(lines.join("\n") + "\n"));
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    let mut changed = lines.clone();
+    changed[0] = "first changed".into();
+    changed[11] = "last changed".into();
+    repo.create_file("file.txt", &(changed.join("\n") + "\n"));
+    let output = repo.run_gust_with_input(&["add", "-p"], "y\nn\n", &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(stdout.contains("@@ -1,4 +1,4 @@") && stdout.contains("+first changed"), "{}", stdout);
+    assert!(stdout.contains("@@ -9,4 +9,4 @@") && stdout.contains("+last changed"), "{}", stdout);
+
+    // Staged and unstaged at the same time
+    let status = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert_eq!(status.matches("M file.txt").count(), 2, "{}", status);
+
+    repo.run_gust(&["commit", "-m", "Second"]);
+    repo.run_gust(&["restore", "file.txt"]);
+    let committed = repo.read_file("file.txt");
+    assert!(committed.starts_with("first changed\n") && committed.ends_with("line 12\n"), "{}", committed);
+}
+
+#[test]
+fn test_add_patch_split_and_edit() {
+    let repo = TestRepo::new("add_patch_split");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "one\ntwo\nthree\nfour\nfive\n");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    repo.create_file("file.txt", "one\nnew two\nthree\nnew four\nfive\n");
+    let output = repo.run_gust_with_input(&["add", "-p", "file.txt"], "s\nn\ne\n", &[("GUST_EDITOR", "sed -i 's/^+new four/+edited four/'")]);
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(stdout.contains("Split into 2 hunks"), "{}", stdout);
+
+    repo.run_gust(&["commit", "-m", "Second"]);
+    repo.run_gust(&["restore", "file.txt"]);
+    assert_eq!(repo.read_file("file.txt"), "one\ntwo\nthree\nedited four\nfive\n");
+}
+
+#[test]
+fn test_add_patch_with_large_unrelated_files() {
+    let repo = TestRepo::new("add_patch_large");
+
+    repo.run_gust(&["init"]);
+    let old: String = (0..4000).map(|i| format!("old {}\n", i)).collect();
+    repo.create_file("file.txt", &old);
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    // Every line differs, so the diff needs as many edits as there are lines in both versions
+    let new: String = (0..4000).map(|i| format!("new {}\n", i)).collect();
+    repo.create_file("file.txt", &new);
+    let output = repo.run_gust_with_input(&["add", "-p", "file.txt"], "y\n", &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(stdout.contains("@@ -1,4000 +1,4000 @@"), "{}", &stdout[..stdout.len().min(500)]);
+
+    repo.run_gust(&["commit", "-m", "Second"]);
+    repo.create_file("file.txt", "");
+    repo.run_gust(&["restore", "file.txt"]);
+    assert_eq!(repo.read_file("file.txt"), new);
+}
+
+#[test]
+fn test_commit_uses_content_from_add_time() {
+    let repo = TestRepo::new("add_snapshot");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    repo.create_file("file.txt", "v2");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.create_file("file.txt", "v3 edited after add");
+    let status = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert_eq!(status.matches("M file.txt").count(), 2, "the file should be staged and modified: {}", status);
+
+    repo.run_gust(&["commit", "-m", "Second"]);
+    assert_eq!(repo.read_file("file.txt"), "v3 edited after add", "committing shouldn't touch the working tree");
+    let status = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status.contains("M file.txt"), "the later edit should still be unstaged: {}", status);
+    repo.run_gust(&["restore", "file.txt"]);
+    assert_eq!(repo.read_file("file.txt"), "v2", "the commit should have the version from add time");
+
+    // Deleting a staged file keeps the staged version
+    repo.create_file("new.txt", "new");
+    repo.run_gust(&["add", "new.txt"]);
+    fs::remove_file(repo.path.join("new.txt")).unwrap();
+    let status = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    assert!(status.contains("+ new.txt") && status.contains("- new.txt"), "{}", status);
+    repo.run_gust(&["commit", "-m", "Third"]);
+    repo.run_gust(&["restore", "new.txt"]);
+    assert_eq!(repo.read_file("new.txt"), "new");
+}
+
+#[test]
+fn test_add_and_status_with_pathspecs() {
+    let repo = TestRepo::new("pathspecs");
+
+    repo.run_gust(&["init"]);
+    for file in ["a.rs", "src/b.rs", "src/sub/test_c.rs", "src/sub/d.rs", "docs/e.md", "docs/x.rs"] {
+        repo.create_file(file, file);
+    }
+    repo.run_gust(&["add", "*.rs", ":(exclude)docs"]);
+    let status = repo.stdout(&["status"]);
+    for staged in ["+ a.rs", "+ src/b.rs", "+ src/sub/test_c.rs", "+ src/sub/d.rs"] {
+        assert_eq!(status.matches(staged).count(), 1, "{} should be staged: {}", staged, status);
+    }
+    assert!(!status[..status.find("Unstaged").unwrap()].contains("docs/"), "docs should be excluded: {}", status);
+
+    let status = repo.stdout(&["status", "src/**/test_*.rs"]);
+    assert!(status.contains("src/sub/test_c.rs") && !status.contains("src/b.rs") && !status.contains("docs"), "{}", status);
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["add", "missing*.txt"]).stderr).to_string();
+    assert!(stderr.contains("did not match any files"), "{}", stderr);
+}
+
+#[test]
+fn test_add_update_all_and_deleted_paths() {
+    let repo = TestRepo::new("add_update_all");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("deleted.txt", "deleted");
+    repo.create_file("modified.txt", "v1");
+    repo.create_file("removed_by_name.txt", "removed");
+    repo.run_gust(&["add", "-A"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    fs::remove_file(repo.path.join("deleted.txt")).unwrap();
+    fs::remove_file(repo.path.join("removed_by_name.txt")).unwrap();
+    repo.create_file("modified.txt", "v2");
+    repo.create_file("new.txt", "new");
+
+    // A deleted file can be staged by its name
+    repo.run_gust(&["add", "removed_by_name.txt"]);
+    repo.run_gust(&["add", "-u"]);
+    let status = repo.stdout(&["status"]);
+    let staged = &status[..status.find("Unstaged").unwrap()];
+    assert!(staged.contains("- deleted.txt") && staged.contains("- removed_by_name.txt") && staged.contains("M modified.txt"), "{}", status);
+    assert!(!staged.contains("new.txt"), "-u shouldn't stage untracked files: {}", status);
+
+    repo.run_gust(&["add", "--all"]);
+    let status = repo.stdout(&["status"]);
+    assert!(status.contains("+ new.txt") && status[status.find("Unstaged").unwrap()..].contains("No changes"), "{}", status);
+}
+
+#[test]
+fn test_rm_deletes_and_stages_removal() {
+    let repo = TestRepo::new("rm_files");
+
+    repo.run_gust(&["init"]);
+    for file in ["deleted.txt", "kept.txt", "gone.txt", "dir/modified.txt"] {
+        repo.create_file(file, file);
+    }
+    repo.run_gust(&["add", "-A"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    repo.run_gust(&["rm", "deleted.txt"]);
+    assert!(!repo.file_exists("deleted.txt"), "rm should delete the file");
+    repo.run_gust(&["rm", "--cached", "kept.txt"]);
+    assert!(repo.file_exists("kept.txt"), "rm --cached should keep the file");
+    // Files that are already gone can still be removed
+    fs::remove_file(repo.path.join("gone.txt")).unwrap();
+    repo.run_gust(&["rm", "gone.txt"]);
+
+    let status = repo.stdout(&["status"]);
+    let (staged, unstaged) = status.split_at(status.find("Unstaged").unwrap());
+    assert!(staged.contains("- deleted.txt") && staged.contains("- kept.txt") && staged.contains("- gone.txt"), "{}", status);
+    assert!(unstaged.contains("+ kept.txt"), "a file removed with --cached should be untracked: {}", status);
+
+    // Local changes would be lost
+    repo.create_file("dir/modified.txt", "changed");
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["rm", "dir"]).stderr).to_string();
+    assert!(stderr.contains("changes that aren't committed") && repo.file_exists("dir/modified.txt"), "{}", stderr);
+    repo.run_gust(&["rm", "-f", "dir"]);
+    assert!(!repo.path.join("dir").exists(), "empty folders should be removed too");
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["rm", "untracked.txt"]).stderr).to_string();
+    assert!(stderr.contains("did not match any tracked files"), "{}", stderr);
+}
+
+#[test]
+fn test_unstage_keeps_working_tree() {
+    let repo = TestRepo::new("unstage");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "content");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["unstage", "file.txt"]);
+
+    let status = String::from_utf8_lossy(&repo.run_gust(&["status"]).stdout).to_string();
+    let (staged, unstaged) = status.split_at(status.find("Unstaged").unwrap());
+    assert!(staged.contains("No changes") && unstaged.contains("+ file.txt"), "{}", status);
+    assert_eq!(repo.read_file("file.txt"), "content");
+}
+
+#[test]
+fn test_mv_stages_renames() {
+    let repo = TestRepo::new("mv_files");
+
+    repo.run_gust(&["init"]);
+    for file in ["old.txt", "src/a.rs", "src/nested/b.rs", "docs/readme.md"] {
+        repo.create_file(file, file);
+    }
+    repo.run_gust(&["add", "-A"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    // Changes that weren't staged move with the file but stay unstaged
+    repo.create_file("old.txt", "edited");
+    repo.run_gust(&["mv", "old.txt", "new.txt"]);
+    repo.run_gust(&["mv", "src", "lib"]);
+    repo.run_gust(&["mv", "docs/readme.md", "lib"]);
+    assert!(!repo.file_exists("old.txt") && repo.read_file("new.txt") == "edited");
+    assert!(repo.file_exists("lib/nested/b.rs") && repo.file_exists("lib/readme.md") && !repo.path.join("src").exists());
+
+    let status = repo.stdout(&["status"]);
+    let (staged, unstaged) = status.split_at(status.find("Unstaged").unwrap());
+    for rename in ["R old.txt -> new.txt", "R src/a.rs -> lib/a.rs", "R src/nested/b.rs -> lib/nested/b.rs", "R docs/readme.md -> lib/readme.md"] {
+        assert!(staged.contains(rename), "missing {}: {}", rename, status);
+    }
+    assert!(unstaged.contains("M new.txt") && !unstaged.contains("old.txt") && !unstaged.contains("src/"), "{}", status);
+
+    let commit = repo.stdout(&["commit", "-m", "Move"]);
+    assert!(commit.contains("R old.txt -> new.txt"), "{}", commit);
+    repo.run_gust(&["add", "-A"]);
+    repo.run_gust(&["commit", "-m", "Edit"]);
+    let status = repo.stdout(&["status"]);
+    assert_eq!(status.matches("No changes").count(), 2, "{}", status);
+
+    // Moving a file back where it's committed leaves nothing to commit
+    repo.run_gust(&["mv", "new.txt", "moved.txt"]);
+    repo.run_gust(&["mv", "moved.txt", "new.txt"]);
+    let status = repo.stdout(&["status"]);
+    assert_eq!(status.matches("No changes").count(), 2, "{}", status);
+
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["mv", "new.txt", "lib/a.rs"]).stderr).to_string();
+    assert!(stderr.contains("already exists"), "{}", stderr);
+    repo.create_file("untracked.txt", "untracked");
+    let stderr = String::from_utf8_lossy(&repo.run_gust(&["mv", "untracked.txt", "other.txt"]).stderr).to_string();
+    assert!(stderr.contains("isn't tracked"), "{}", stderr);
+}
+
+#[test]
+fn test_mv_then_add_and_unstage() {
+    let repo = TestRepo::new("mv_add");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("a.txt", "content");
+    repo.run_gust(&["add", "a.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    // Adding everything after a move keeps the rename instead of a removal and a new file
+    repo.run_gust(&["mv", "a.txt", "b.txt"]);
+    repo.create_file("b.txt", "changed");
+    repo.run_gust(&["add", "-A"]);
+    let status = repo.stdout(&["status"]);
+    assert!(status.contains("R a.txt -> b.txt") && !status.contains("- a.txt"), "{}", status);
+
+    // Unstaging the old path undoes the rename in the staging area
+    repo.run_gust(&["unstage", "a.txt"]);
+    let status = repo.stdout(&["status"]);
+    let unstaged = &status[status.find("Unstaged").unwrap()..];
+    assert!(unstaged.contains("- a.txt") && unstaged.contains("+ b.txt"), "{}", status);
+}
+
+#[test]
+fn test_status_detects_renames_and_copies() {
+    let repo = TestRepo::new("rename_detection");
+    let lines: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+
+    repo.run_gust(&["init"]);
+    repo.create_file("similar.txt", &lines);
+    repo.create_file("same.txt", "same content\n");
+    repo.create_file("source.txt", "copied content\n");
+    repo.run_gust(&["add", "-A"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+
+    fs::rename(repo.path.join("same.txt"), repo.path.join("renamed.txt")).unwrap();
+    fs::remove_file(repo.path.join("similar.txt")).unwrap();
+    repo.create_file("edited.txt", &format!("{}line 11\n", lines));
+    repo.create_file("copy.txt", "copied content\n");
+
+    // Identical files are always paired, similar ones and copies only when asked for
+    let status = repo.stdout(&["status"]);
+    assert!(status.contains("R same.txt -> renamed.txt (100%)"), "{}", status);
+    assert!(status.contains("- similar.txt") && status.contains("+ edited.txt") && status.contains("+ copy.txt"), "{}", status);
+    let status = repo.stdout(&["status", "-M", "-C"]);
+    assert!(status.contains("R similar.txt -> edited.txt (89%)") && !status.contains("- similar.txt"), "{}", status);
+    assert!(status.contains("C source.txt -> copy.txt (100%)"), "{}", status);
+    let status = repo.stdout(&["status", "--find-renames=95"]);
+    assert!(status.contains("- similar.txt") && status.contains("+ edited.txt"), "{}", status);
+
+    // Staged removals and new files are paired the same way
+    repo.run_gust(&["add", "-A"]);
+    let status = repo.stdout(&["status", "-M"]);
+    let (staged, unstaged) = status.split_at(status.find("Unstaged").unwrap());
+    assert!(staged.contains("R same.txt -> renamed.txt (100%)") && staged.contains("R similar.txt -> edited.txt (89%)"), "{}", status);
+    assert!(unstaged.contains("No changes"), "{}", status);
+}
+
+#[test]
+fn test_log_stat_and_diff_report_renames() {
+    let repo = TestRepo::new("rename_reporting");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("old.txt", "one\ntwo\nthree\nfour\n");
+    repo.create_file("other.txt", "other\n");
+    repo.run_gust(&["add", "-A"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.run_gust(&["mv", "old.txt", "new.txt"]);
+    repo.create_file("new.txt", "one\ntwo\nthree\nfour\nfive\n");
+    repo.create_file("other.txt", "changed\n");
+    repo.run_gust(&["add", "-A"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    let log = repo.stdout(&["log", "--stat", "-M"]);
+    let second = &log[log.find("Second").unwrap()..log.find("First").unwrap()];
+    assert!(second.contains("R old.txt -> new.txt (79%) | 1 +"), "{}", log);
+    assert!(second.contains("M other.txt") && second.contains("| 2 +-"), "{}", log);
+    assert!(second.contains("2 files changed, 2 insertions(+), 1 deletion(-)"), "{}", log);
+    // Without -M, the rename is too different to be paired
+    let log = repo.stdout(&["log", "--stat"]);
+    assert!(log.contains("- old.txt") && log.contains("+ new.txt"), "{}", log);
+
+    let diff = repo.stdout(&["diff", "HEAD~1", "HEAD", "-M"]);
+    assert!(diff.contains("diff --gust a/old.txt b/new.txt\nsimilarity index 79%\nrename from old.txt\nrename to new.txt"), "{}", diff);
+    assert!(diff.contains("@@ -2,3 +2,4 @@\n two\n three\n four\n+five"), "{}", diff);
+
+    // Without revisions, diff shows what isn't staged, and --staged what is
+    repo.create_file("other.txt", "unstaged\n");
+    let diff = repo.stdout(&["diff"]);
+    assert!(diff.contains("-changed\n+unstaged"), "{}", diff);
+    repo.run_gust(&["mv", "new.txt", "moved.txt"]);
+    let diff = repo.stdout(&["diff", "--staged"]);
+    assert!(diff.contains("similarity index 100%\nrename from new.txt\nrename to moved.txt") && !diff.contains("other.txt"), "{}", diff);
+}
+
+#[test]
+fn test_diff_revision_against_working_tree() {
+    let repo = TestRepo::new("diff_revision");
+
+    repo.run_gust(&["init"]);
+    repo.create_file("file.txt", "v1\n");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "First"]);
+    repo.create_file("file.txt", "v2\n");
+    repo.run_gust(&["add", "file.txt"]);
+    repo.run_gust(&["commit", "-m", "Second"]);
+
+    // Staged and unstaged changes are both shown, untracked files aren't
+    repo.create_file("file.txt", "v3\n");
+    repo.create_file("staged.txt", "staged\n");
+    repo.run_gust(&["add", "staged.txt"]);
+    repo.create_file("untracked.txt", "untracked\n");
+    let diff = repo.stdout(&["diff", "HEAD~1"]);
+    assert!(diff.contains("-v1\n+v3"), "{}", diff);
+    assert!(diff.contains("new file, regular\n--- /dev/null\n+++ b/staged.txt"), "{}", diff);
+    assert!(!diff.contains("untracked.txt"), "{}", diff);
+}